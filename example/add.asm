@@ -1,10 +1,9 @@
-push 512
 !define top <[-0]
-!define rax [0:64]
-!define rbx [64:64]
-!define rcx [128:64]
-!define rdx [192:64]
-!define rex [256:64]
+!var rax 64
+!var rbx 64
+!var rcx 64
+!var rdx 64
+!var rex 64
 
 .first-start
 	ext print, "Input first number to add: "
@@ -1,3 +1,4 @@
+!export included_function
 
 .included_function
 	ext print, "I've been included!\n"
@@ -1,5 +1,5 @@
 jmp start
-!include example/included.asm
+!include included.asm
 
 .start
 	call included_function
@@ -1,118 +1,204 @@
 extern crate gmp;
 use super::value::Value;
 
-const COMMENT_CHAR:char = ';';
-const ARGUMENT_CHAR:char = ',';
-
 use super::environment::Environment;
 use super::instruction::Instruction;
 use super::instruction::create_instruction;
 use super::error::*;
+use super::span::Span;
+use super::lexer::*;
 use super::util::*;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+//how deep macros may be expanded inside of other macros
+const MAX_MACRO_DEPTH:usize = 64;
+//how many lines !rep and !for may produce in total, to catch loops that never end
+const MAX_LOOP_LINES:usize = 1 << 16;
+
+//a block of lines between !macro and !endmacro
+#[derive(Clone)]
+struct Macro {
+	params:   Vec<String>,
+	lines:    Vec<(String, usize, Option<String>)>,
+	location: Error
+}
+
+//what a block of recorded lines is used for once its end is reached
+enum Block {
+	Macro(String),
+	Rep(usize),
+	For(String, usize, usize)
+}
+
+//the block whose lines are currently being read
+struct Recording {
+	block: Block,
+	depth: usize,
+	body:  Macro
+}
+
+impl Block {
+	//the directives that start and end this kind of block
+	fn directives(&self) -> (&'static str, &'static str) {
+		match *self {
+			Block::Macro(_) => ("macro", "endmacro"),
+			Block::Rep(_) => ("rep", "endrep"),
+			Block::For(..) => ("for", "endfor")
+		}
+	}
+}
+
+//a block between !if and !endif
+struct Condition {
+	active:   bool,
+	taken:    bool,
+	parent:   bool,
+	has_else: bool,
+	location: Error
+}
+
+//the fields of a !struct as (name, offset, size), fields of nested structs are named 'outer.inner'
+struct Layout {
+	fields:   Vec<(String, usize, usize)>,
+	size:     usize,
+	location: Error
+}
+
 pub struct Assembler {
 	code:       Vec<Box<Instruction>>,
+	spans:      Vec<Span>,
 	pub labels: HashMap<String, usize>,
-	defines:    Vec<(String, String)>,
-	pub ext_calls:  HashMap<String, Box<Fn(&Value, &mut Environment, &Assembler)>>,
-	pub print_parsed: bool
+	//every label by the file it is defined in and its name, only exported labels are in 'labels'
+	file_labels: HashMap<(String, String), usize>,
+	label_locations: HashMap<(String, String), Error>,
+	exports:    Vec<(String, String, Error)>,
+	//the file and global label that each instruction's labels are resolved in
+	scopes:     Vec<(String, String)>,
+	scope:      String,
+	//the bits of the variables and data that are pushed before the first instruction runs
+	statics:    Vec<bool>,
+	variables:  HashMap<String, Error>,
+	//set once a run leaves bits above the variables, where new variables would have to go
+	statics_locked: bool,
+	structs:    HashMap<String, Layout>,
+	//the name, fields read so far and location of a !struct that spans multiple lines
+	pending_struct: Option<(String, String, Error)>,
+	//the value of every defined name, and where it was defined if that was in the source
+	defines:    HashMap<String, (String, Option<Error>)>,
+	macros:     HashMap<String, Macro>,
+	recording:  Option<Recording>,
+	expansions: usize,
+	macro_depth: usize,
+	//lines produced by the outermost loop that is being expanded and the loops inside of it
+	loop_lines: usize,
+	loop_depth: usize,
+	conditions: Vec<Condition>,
+	include_paths: Vec<PathBuf>,
+	//the files that are currently being read, outermost first
+	include_stack: Vec<(PathBuf, String)>,
+	once_files: HashSet<PathBuf>,
+	pub ext_calls:  HashMap<String, Box<Fn(&Value, &mut Environment, &Assembler) -> Result<(), RuntimeErrorType>>>,
+	//warnings and notes from !warning and !print, in the order they were found
+	pub diagnostics: Vec<AssembleError>,
+	pub print_parsed: bool,
+	//when set, every expanded line is also kept so it can be written out by 'preprocessed'
+	pub preprocess: bool,
+	preprocessed: Vec<String>,
+	pub trace: bool
 }
 
-impl Assembler {
-	fn parse_strings(&self, line:&String) -> String {
-		let mut new_line:String = String::new();
-		let mut is_literal_char = false;
-		let mut is_in_stringval = false;
-		for c in line.chars() {
-			if !is_in_stringval && c == '"' {
-				is_in_stringval = true;
-				is_literal_char = false;
-				new_line.push('b');
-				continue;
-			}
-			if is_in_stringval {
-				if c == '\\' && !is_literal_char {
-					is_literal_char = true;
-					continue;
-				}
-				if c == '"' && !is_literal_char {
-					is_in_stringval = false;
-					continue;
-				}
-				let mut char_add:char = c;
-				if is_literal_char {
-					char_add = match c {
-						'\\' => '\\',
-						'n'  => '\n',
-						't'  => '\t',
-						other => other
-					};
-					is_literal_char = false;
-				}
-				for b in char_to_boolvec(char_add) {
-					new_line.push(match b {
-						true => '1',
-						false => '0'
-					})
-				}
-			} else {
-				new_line.push(c);
-			}
+//the text of a message argument, which is either a single string literal or the source text
+fn string_text(tokens: &[Token], line: &str) -> String {
+	match tokens {
+		&[Token{kind: TokenKind::Str(ref text), ..}] => String::from_utf8_lossy(text).to_string(),
+		_ => source_text(tokens, line).to_string()
+	}
+}
+
+//the text of 'tokens' with string and character literals written as literals of their exact bits
+fn lower_literals(tokens: &[Token], source: &str) -> String {
+	let mut ret = String::new();
+	let mut last = match tokens.first() {
+		Some(token) => token.start,
+		None => return ret
+	};
+	for token in tokens {
+		ret.push_str(&source[last..token.start]);
+		match token.kind {
+			TokenKind::Str(ref bytes) | TokenKind::Char(ref bytes) => {
+				let bits = bytes_to_boolvec(bytes);
+				ret.push_str(&format!("{}'h{}", bits.len(), boolvec_to_bignum(&bits).to_str_radix(16)));
+			},
+			_ => ret.push_str(token.text(source))
 		}
-		return new_line;
+		last = token.end;
 	}
+	ret
+}
 
+//creates a span for the code on 'line', leaving out its indentation and comments
+fn code_span(line: &str, linenum: usize, filename: Option<String>) -> Span {
+	let mut tokens = tokenize(line).unwrap_or_default();
+	strip_comments(&mut tokens);
+	match tokens.is_empty() {
+		true => Span::from_line(line, 0, line.len(), linenum, filename),
+		false => Span::from_line(line, tokens[0].start, tokens[tokens.len() - 1].end, linenum, filename)
+	}
+}
+
+fn flush_stdout() -> Result<(), RuntimeErrorType> {
+	match io::stdout().flush() {
+		Ok(_) => Ok(()),
+		Err(err) => Err(RuntimeErrorType::Io(format!("{}", err)))
+	}
+}
+
+impl Assembler {
 	pub fn add_external_call<F>(&mut self, name: &str, external: F)
-		where F : 'static + Fn(&Value, &mut Environment, &Assembler) {
+		where F : 'static + Fn(&Value, &mut Environment, &Assembler) -> Result<(), RuntimeErrorType> {
 
 		self.ext_calls.insert(name.to_string(), Box::new(external));
 	}
 
 	fn add_default_external_calls(asm: &mut Assembler) {
 		asm.add_external_call("printnum", |v,e,_| {
-			print!("{}", boolvec_to_bignum(v.get_boolvec(e).as_slice()));
-			io::stdout().flush().ok().expect("Could not flush stdout");
+			print!("{}", boolvec_to_bignum(v.get_boolvec(e)?.as_slice()));
+			flush_stdout()
 		});
 		asm.add_external_call("print", |v,e,_| {
-			let mut chars:Vec<u8> = vec![];
-			let boolvec = v.get_boolvec(e);
-			let val = boolvec.as_slice();
-			for i in 0..(val.len()/8) {
-				let nums = &val[i*8..(i+1)*8];
-				let c = boolvec_to_u8(nums);
-				if c == 0 {
-					break;
-				}
-				chars.push(c);
-			}
-			let s = String::from_utf8_lossy(chars.as_slice()).to_string();
-			print!("{}", s);
-			io::stdout().flush().ok().expect("Could not flush stdout");
+			print!("{}", boolvec_to_string(&v.get_boolvec(e)?));
+			flush_stdout()
 		});
 		asm.add_external_call("valid", |v,e,_a| {
-			let pos = v.get_ptr_position(e);
+			let pos = v.get_ptr_position(e)?;
 			let num = match e.validity {
 				true => gmp::mpz::Mpz::one(),
 				false => gmp::mpz::Mpz::zero()
 			};
-			let size = v.get_size(e);
-			e.set_bits_bignum(&num, pos, size);
+			let size = v.get_size(e)?;
+			e.set_bits_bignum(&num, pos, size)
 		});
 		asm.add_external_call("prompt", |_v,e,_a|{
 			let mut input = String::new();
-			io::stdin().read_line(&mut input).expect("Invalid Input!");
+			if let Err(err) = io::stdin().read_line(&mut input) {
+				return Err(RuntimeErrorType::Io(format!("{}", err)));
+			}
 			e.input_string = "".to_string();
 
 			for line in input.lines() {
 				e.input_string.push_str(line.as_ref());
 			}
+			Ok(())
 		});
 		asm.add_external_call("inputnumlen", |v,e,_|{
 			e.validity = true;
@@ -124,21 +210,21 @@ impl Assembler {
 				}
 			};
 			let num_size = num.bit_length();
-			let pos = v.get_ptr_position(e);
-			let size = v.get_ptr_size(e);
-			e.set_bits_usize(num_size, pos, size);
+			let pos = v.get_ptr_position(e)?;
+			let size = v.get_ptr_size(e)?;
+			e.set_bits_usize(num_size, pos, size)
 		});
 		asm.add_external_call("inputlen", |v,e,_|{
 			let len_bits = e.input_string.len() * 8;
-			let pos = v.get_ptr_position(e);
-			let size = v.get_size(e);
-			e.set_bits_usize(len_bits, pos, size);
+			let pos = v.get_ptr_position(e)?;
+			let size = v.get_size(e)?;
+			e.set_bits_usize(len_bits, pos, size)
 		});
 		asm.add_external_call("input", |v,e,_|{
 			let boolvec = str_to_boolvec(e.input_string.as_ref());
-			let pos = v.get_ptr_position(e);
-			let size = v.get_size(e);
-			e.set_bits_boolvec(boolvec.as_slice(), pos, size);
+			let pos = v.get_ptr_position(e)?;
+			let size = v.get_size(e)?;
+			e.set_bits_boolvec(boolvec.as_slice(), pos, size)
 		});
 		asm.add_external_call("inputnum", |v,e,_|{
 			e.validity = true;
@@ -149,170 +235,1841 @@ impl Assembler {
 					gmp::mpz::Mpz::zero()
 				}
 			};
-			let pos = v.get_ptr_position(e);
-			let size = v.get_size(e);
-			e.set_bits_bignum(&num, pos, size);
+			let pos = v.get_ptr_position(e)?;
+			let size = v.get_size(e)?;
+			e.set_bits_bignum(&num, pos, size)
+		});
+
+		asm.add_external_call("argc", |v,e,_|{
+			let argc = e.args.len();
+			let pos = v.get_ptr_position(e)?;
+			let size = v.get_size(e)?;
+			e.set_bits_usize(argc, pos, size)
+		});
+		//'arglen' and 'arg' read the index of the argument from the pointer, and then overwrite it
+		asm.add_external_call("arglen", |v,e,_|{
+			let index = v.get_usize(e)?;
+			e.validity = index < e.args.len();
+			let len_bits = match e.args.get(index) {
+				Some(arg) => arg.len() * 8,
+				None => 0
+			};
+			let pos = v.get_ptr_position(e)?;
+			let size = v.get_size(e)?;
+			e.set_bits_usize(len_bits, pos, size)
+		});
+		asm.add_external_call("arg", |v,e,_|{
+			let index = v.get_usize(e)?;
+			e.validity = index < e.args.len();
+			let boolvec = match e.args.get(index) {
+				Some(arg) => str_to_boolvec(arg),
+				None => vec![]
+			};
+			let pos = v.get_ptr_position(e)?;
+			let size = v.get_size(e)?;
+			e.set_bits_boolvec(boolvec.as_slice(), pos, size)
+		});
+		//stores the variable in the input, like 'prompt'
+		asm.add_external_call("getenv", |v,e,_|{
+			let name = boolvec_to_string(&v.get_boolvec(e)?);
+			match env::var(&name) {
+				Ok(value) => {
+					e.validity = true;
+					e.input_string = value;
+				},
+				Err(_) => {
+					e.validity = false;
+					e.input_string = "".to_string();
+				}
+			}
+			Ok(())
+		});
+
+		asm.add_external_call("exit", |v,e,_|{
+			e.halt(&v.get_boolvec(e)?);
+			Ok(())
 		});
 
 		asm.add_external_call("random", move |v,e,_|{
-			let val = v.get_bignum(e);
+			let val = v.get_bignum(e)?;
 			let result = e.randstate.urandom(&val);
-			let pos = v.get_ptr_position(e);
-			let size = v.get_ptr_size(e);
-			e.set_bits_bignum(&result, pos, size);
+			let pos = v.get_ptr_position(e)?;
+			let size = v.get_ptr_size(e)?;
+			e.set_bits_bignum(&result, pos, size)
 		});
 	}
 
 	pub fn new(do_print_parsed: bool) -> Assembler {
 		let mut asm = Assembler {
 			code:         Vec::new(),
+			spans:        Vec::new(),
 			labels:       HashMap::new(),
-			defines:      Vec::new(),
+			file_labels:  HashMap::new(),
+			label_locations: HashMap::new(),
+			exports:      Vec::new(),
+			scopes:       Vec::new(),
+			scope:        String::new(),
+			statics:      Vec::new(),
+			variables:    HashMap::new(),
+			statics_locked: false,
+			structs:      HashMap::new(),
+			pending_struct: None,
+			defines:      HashMap::new(),
+			macros:       HashMap::new(),
+			recording:    None,
+			expansions:   0,
+			macro_depth:  0,
+			loop_lines:   0,
+			loop_depth:   0,
+			conditions:   Vec::new(),
+			include_paths: Vec::new(),
+			include_stack: Vec::new(),
+			once_files:   HashSet::new(),
 			ext_calls:    HashMap::new(),
-			print_parsed: do_print_parsed
+			diagnostics:  Vec::new(),
+			print_parsed: do_print_parsed,
+			preprocess:   false,
+			preprocessed: Vec::new(),
+			trace:        false
 		};
 		Assembler::add_default_external_calls(&mut asm);
 		asm
 	}
 
 	//private because reasons
-	fn parse_args(&mut self, iname: &String, arguments: &[&str], err: &Error){
-		let instruction = create_instruction(iname.as_ref(), arguments, self, err);
+	fn parse_args(&mut self, iname: &String, arguments: &[&str], err: &Error)
+	-> Result<(), AssembleError> {
+		let instruction = create_instruction(iname.as_ref(), arguments, self, err)?;
 		self.code.push(instruction);
+		self.spans.push(err.span().clone());
+		self.scopes.push((self.file_scope(), self.scope.clone()));
+		Ok(())
 	}
 
-	fn parse_macros(&mut self, line: &String, err: &Error) -> bool {
+	fn parse_macros(&mut self, line: &str, tokens: &[Token], err: &Error) -> Result<bool, AssembleError> {
 		//macro
-		if line.chars().next() == Some('!') {
-			let macro_text = line[1..].trim();
-			let mut macro_args:Vec<&str> = macro_text.split_whitespace().collect();
-			if macro_args.len() < 1 {
-				err.throw(ErrorType::Empty("macro".to_string()))
-			}
-			let macro_name = macro_args[0];
-			let macro_total_args:&str = &macro_text[macro_name.len()..].trim();
-			macro_args.remove(0);
+		if tokens[0].kind == TokenKind::Bang {
+			let macro_name = match tokens.get(1) {
+				Some(&Token{kind: TokenKind::Ident(ref name), ..}) => name.as_ref(),
+				_ => return Err(err.error(ErrorType::Empty("macro".to_string())))
+			};
+			let macro_args = &tokens[2..];
 			match macro_name {
 				"define" => {
-					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(2));
-					let name = macro_args[0].to_string();
-					let args = macro_args[1..].join(" ");
-					self.defines.push((name, args));
+					let name_end = match macro_args.len() {
+						0 => 0,
+						_ => word_end(macro_args, 0)
+					};
+					err.check_args("macro", macro_name, name_end, ArgumentType::AtLeast(1))?;
+					//a name without a value is defined as 1, like --define
+					let value = match source_text(&macro_args[name_end..], line) {
+						"" => "1",
+						value => value
+					};
+					let name = source_text(&macro_args[..name_end], line).to_string();
+					self.define_at(&name, value, err);
+				},
+				"undef" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(1))?;
+					self.undefine(source_text(macro_args, line));
 				},
 				"include" => {
-					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(1));
-					let file = File::open(macro_total_args).unwrap();
-					let buffer = BufReader::new(&file);
-					let mut linenum = 0;
-					for line in buffer.lines() {
-						linenum += 1;
-						let l:String = line.unwrap();
-						self.parse_line(&l, linenum, Some(macro_total_args.to_string()));
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(1))?;
+					let file_name = string_text(macro_args, line);
+					self.include_file(&file_name, err)?;
+				},
+				"export" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(1))?;
+					for name in split_arguments(macro_args) {
+						let name = source_text(name, line).to_string();
+						self.exports.push((self.file_scope(), name, err.clone()));
+					}
+				},
+				"data" | "zero" | "incbin" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(2))?;
+					let name_end = word_end(macro_args, 0);
+					let name = source_text(&macro_args[..name_end], line).to_string();
+					let value = &macro_args[name_end..];
+					err.check_args("macro", macro_name, value.len() + 1, ArgumentType::AtLeast(2))?;
+					let bits = match macro_name {
+						"data" => self.evaluate_data(line, value, err)?,
+						"zero" => vec![false; self.evaluate_size(line, value, err)?],
+						_ => {
+							let file_name = string_text(value, line);
+							let path = self.resolve_include(&file_name, err)?;
+							match fs::read(&path) {
+								Ok(bytes) => bytes_to_boolvec(&bytes),
+								Err(e) => return Err(err.error(ErrorType::FileError{
+									file: file_name,
+									reason: format!("{}", e)
+								}))
+							}
+						}
+					};
+					self.declare_data(&name, &bits, err)?;
+				},
+				"var" | "array" => {
+					//the name is a single word, the width and count are expressions separated by commas
+					let name_end = match macro_args.len() {
+						0 => 0,
+						_ => word_end(macro_args, 0)
+					};
+					let words = split_arguments(&macro_args[name_end..]);
+					let num = match macro_name {
+						"var" => 2,
+						_ => 3
+					};
+					err.check_args("macro", macro_name, words.len() + 1, ArgumentType::Exact(num))?;
+					let name = source_text(&macro_args[..name_end], line).to_string();
+					let fields = match words[0] {
+						&[Token{kind: TokenKind::Ident(ref typename), ..}] => self.structs.get(typename)
+							.map(|layout| (layout.size, layout.fields.clone())),
+						_ => None
+					};
+					let width = match fields {
+						Some((size, _)) => size,
+						None => self.evaluate_size(line, words[0], err)?
+					};
+					let count = match words.get(1) {
+						Some(count) => self.evaluate_size(line, count, err)?,
+						None => 1
+					};
+					let offset = self.declare_variable(&name, width * count, err)?;
+					//a variable of a struct type can be used as [name.field]
+					if let (Some((_, fields)), "var") = (fields, macro_name) {
+						for (field, field_offset, size) in fields {
+							self.define_at(&format!("{}.{}", name, field), &format!("{}:{}", offset + field_offset, size), err);
+						}
+					}
+					if macro_name == "array" {
+						self.define_at(&format!("{}.width", name), &width.to_string(), err);
+						self.define_at(&format!("{}.count", name), &count.to_string(), err);
+					}
+				},
+				"struct" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(2))?;
+					let name = match macro_args[0].kind {
+						TokenKind::Ident(ref name) => name.clone(),
+						_ => return Err(err.error(ErrorType::InvalidValue(
+							macro_args[0].text(line).to_string()
+						)))
+					};
+					if macro_args[1].kind != TokenKind::Symbol('{') {
+						return Err(err.error(ErrorType::Generic(format!(
+							"expected '{{' after the name of struct '{}'", name
+						))));
+					}
+					if let Some(previous) = self.structs.get(&name) {
+						return Err(err.error(ErrorType::Duplicate{
+							typename: "struct".to_string(),
+							value: name,
+							previous: previous.location.location()
+						}));
+					}
+					self.pending_struct = Some((name, String::new(), err.clone()));
+					self.parse_struct_fields(source_text(&macro_args[2..], line))?;
+				},
+				"assert" => {
+					let args = split_arguments(macro_args);
+					err.check_args("macro", macro_name, args.len(), ArgumentType::Range(1, 2))?;
+					if self.evaluate_constant(line, args[0], err)? == gmp::mpz::Mpz::zero() {
+						let message = match args.get(1) {
+							Some(message) => string_text(message, line),
+							None => format!("'{}' does not hold", source_text(args[0], line))
+						};
+						return Err(err.error(ErrorType::Assertion(message)));
+					}
+				},
+				"error" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(1))?;
+					return Err(err.error(ErrorType::UserError(string_text(macro_args, line))));
+				},
+				"warning" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(1))?;
+					self.diagnostics.push(err.error(ErrorType::Warning(string_text(macro_args, line))));
+				},
+				"print" => {
+					let args = split_arguments(macro_args);
+					err.check_args("macro", macro_name, args.len(), ArgumentType::AtLeast(1))?;
+					let mut parts = Vec::new();
+					for arg in args {
+						parts.push(match arg {
+							&[Token{kind: TokenKind::Str(_), ..}] => string_text(arg, line),
+							_ => format!("{}", self.evaluate_constant(line, arg, err)?)
+						});
+					}
+					self.diagnostics.push(err.error(ErrorType::Note(parts.join(" "))));
+				},
+				"once" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::Exact(0))?;
+					if let Some(&(ref path, _)) = self.include_stack.last() {
+						self.once_files.insert(path.clone());
 					}
 				},
-				name => err.throw(ErrorType::NonExistent{
+				"macro" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(1))?;
+					let name = match macro_args[0].kind {
+						TokenKind::Ident(ref name) => name.clone(),
+						_ => return Err(err.error(ErrorType::InvalidValue(
+							macro_args[0].text(line).to_string()
+						)))
+					};
+					let mut params = Vec::new();
+					for param in split_arguments(&macro_args[1..]) {
+						match param {
+							&[Token{kind: TokenKind::Ident(ref param), ..}] => params.push(param.clone()),
+							_ => return Err(err.error(ErrorType::Generic(format!(
+								"macro parameter '{}' must be a name", source_text(param, line)
+							))))
+						}
+					}
+					if let Some(previous) = self.macros.get(&name) {
+						return Err(err.error(ErrorType::Duplicate{
+							typename: "macro".to_string(),
+							value: name,
+							previous: previous.location.location()
+						}));
+					}
+					self.record(Block::Macro(name), params, err);
+				},
+				"rep" => {
+					err.check_args("macro", macro_name, macro_args.len(), ArgumentType::AtLeast(1))?;
+					let count = self.evaluate_size(line, macro_args, err)?;
+					self.record(Block::Rep(count), Vec::new(), err);
+				},
+				"for" => {
+					//e.g. '!for i in 0..8', the range doesn't include its end
+					let range = match (macro_args.get(0), macro_args.get(1)) {
+						(Some(&Token{kind: TokenKind::Ident(ref name), ..}), Some(keyword)) if keyword.is_ident("in") => {
+							let range = source_text(&macro_args[2..], line);
+							range.find("..").map(|split| (name.clone(), &range[..split], &range[split + 2..]))
+						},
+						_ => None
+					};
+					let (name, start, end) = match range {
+						Some(range) => range,
+						None => return Err(err.error(ErrorType::Generic(
+							"expected '!for [name] in [start]..[end]'".to_string()
+						)))
+					};
+					let start = self.evaluate_size_text(start, err)?;
+					let end = self.evaluate_size_text(end, err)?;
+					self.record(Block::For(name, start, end), Vec::new(), err);
+				},
+				"endmacro" | "endrep" | "endfor" => return Err(err.error(ErrorType::Generic(format!(
+					"'!{}' without a matching '!{}'", macro_name, &macro_name[3..]
+				)))),
+				name => return Err(err.error(ErrorType::NonExistent{
 					typename:"macro".to_string(),
 					value:name.to_string()
-				})
+				}))
+			}
+			return Ok(true);
+		}
+		return Ok(false);
+	}
+
+	//defines a name as if it was defined with !define at the start of the program
+	pub fn define(&mut self, name: &str, value: &str) {
+		self.defines.insert(name.to_string(), (value.to_string(), None));
+	}
+
+	pub fn undefine(&mut self, name: &str) {
+		self.defines.remove(name);
+	}
+
+	//defines a name from the source, with a warning if it already had a value
+	fn define_at(&mut self, name: &str, value: &str, err: &Error) {
+		let previous = self.defines.insert(name.to_string(), (value.to_string(), Some(err.clone())));
+		if let Some((_, location)) = previous {
+			let location = match location {
+				Some(location) => location.location(),
+				None => "the command line".to_string()
+			};
+			self.diagnostics.push(err.error(ErrorType::Warning(format!(
+				"'{}' was already defined on {}, it now has the value '{}'", name, location, value
+			))));
+		}
+	}
+
+	//whether the lines outside of all !if blocks are assembled
+	fn is_active(&self) -> bool {
+		match self.conditions.last() {
+			Some(condition) => condition.active,
+			None => true
+		}
+	}
+
+	//whether the condition of an !if, !ifdef, !ifndef or !elif holds
+	fn evaluate_condition(&self, directive: &str, line: &str, args: &[Token], err: &Error) -> Result<bool, AssembleError> {
+		match directive {
+			"ifdef" | "ifndef" => {
+				err.check_args("macro", directive, args.len(), ArgumentType::Exact(1))?;
+				let is_defined = self.defines.contains_key(args[0].text(line));
+				Ok(is_defined == (directive == "ifdef"))
+			},
+			_ => {
+				err.check_args("macro", directive, args.len(), ArgumentType::AtLeast(1))?;
+				Ok(self.evaluate_constant(line, args, err)? != gmp::mpz::Mpz::zero())
+			}
+		}
+	}
+
+	//the value of an expression that only uses literals and defined names
+	fn evaluate_constant(&self, line: &str, tokens: &[Token], err: &Error) -> Result<gmp::mpz::Mpz, AssembleError> {
+		let expression = self.parse_defines(line, tokens, err)?;
+		match Value::new(&expression, err, false)?.constant() {
+			Some(num) => Ok(num),
+			None => Err(err.error(ErrorType::Generic(format!(
+				"'{}' is not a constant expression", expression
+			))))
+		}
+	}
+
+	fn evaluate_size_text(&self, text: &str, err: &Error) -> Result<usize, AssembleError> {
+		match tokenize(text) {
+			Ok(tokens) => self.evaluate_size(text, &tokens, err),
+			Err(kind) => Err(err.error(kind))
+		}
+	}
+
+	//a constant expression that is used as a size, which can't be negative or too big
+	fn evaluate_size(&self, line: &str, tokens: &[Token], err: &Error) -> Result<usize, AssembleError> {
+		let num = self.evaluate_constant(line, tokens, err)?;
+		match bignum_to_usize(&num) {
+			Some(size) => Ok(size),
+			None => Err(err.error(ErrorType::InvalidValue(source_text(tokens, line).to_string())))
+		}
+	}
+
+	//reads the fields of a !struct until its closing '}'
+	fn parse_struct_fields(&mut self, text: &str) -> Result<(), AssembleError> {
+		let (name, mut body, location) = match self.pending_struct.take() {
+			Some(pending) => pending,
+			None => return Ok(())
+		};
+		match text.find('}') {
+			Some(end) => {
+				if !text[end + 1..].trim().is_empty() {
+					return Err(location.error(ErrorType::Generic(format!(
+						"unexpected '{}' after the end of struct '{}'", text[end + 1..].trim(), name
+					))));
+				}
+				body.push_str(&text[..end]);
+				self.define_struct(name, &body, location)
+			},
+			None => {
+				//every line can hold one or more fields
+				body.push_str(text);
+				body.push(',');
+				self.pending_struct = Some((name, body, location));
+				Ok(())
 			}
-			return true;
 		}
-		return false;
 	}
 
-	fn parse_defines(&self, line:&String) -> String {
-		let mut sepchars:Vec<char> = Vec::new();
-		let strings:Vec<&str> = line.split(|c:char| {
-			if c.is_whitespace() {
-				sepchars.push(c);
-				return true;
+	//lays out the fields of a struct one after another and defines their offsets and sizes
+	fn define_struct(&mut self, name: String, body: &str, err: Error) -> Result<(), AssembleError> {
+		let tokens = match tokenize(body) {
+			Ok(tokens) => tokens,
+			Err(kind) => return Err(err.error(kind))
+		};
+		let mut fields:Vec<(String, usize, usize)> = Vec::new();
+		let mut size = 0;
+		for field in split_arguments(&tokens) {
+			let field_name = match (field.get(0), field.get(1)) {
+				(Some(&Token{kind: TokenKind::Ident(ref field_name), ..}), Some(&Token{kind: TokenKind::Colon, ..}))
+					if field.len() > 2 => field_name.clone(),
+				_ => return Err(err.error(ErrorType::Generic(format!(
+					"'{}' is not a field, fields look like 'name: width'", source_text(field, body)
+				))))
+			};
+			if fields.iter().any(|field| field.0 == field_name) {
+				return Err(err.error(ErrorType::Generic(format!(
+					"struct '{}' has more than one field named '{}'", name, field_name
+				))));
 			}
-			match c {
-				',' | '[' | ']' | ':' | '-' | '<' | '>' => {
-					sepchars.push(c);
-					true
+			//the width of a field can also be another struct
+			let nested = match &field[2..] {
+				&[Token{kind: TokenKind::Ident(ref typename), ..}] => self.structs.get(typename)
+					.map(|layout| (layout.size, layout.fields.clone())),
+				_ => None
+			};
+			let width = match nested {
+				Some((width, ref inner)) => {
+					for &(ref inner_name, offset, inner_size) in inner {
+						fields.push((format!("{}.{}", field_name, inner_name), size + offset, inner_size));
+					}
+					width
 				},
-				_ => false
-			}
-		}).collect::<Vec<&str>>();
-		sepchars.reverse();
-		strings.iter().map(|w|{
-			let mut ret = String::new();
-			let mut did = false;
-			for def in &self.defines {
-				if def.0 == *w {
-					ret.push_str(&format!("{}", def.1));
-					did = true;
-					break;
+				None => self.evaluate_size(body, &field[2..], &err)?
+			};
+			fields.push((field_name, size, width));
+			size += width;
+		}
+		for &(ref field, offset, width) in &fields {
+			self.define_at(&format!("{}.{}", name, field), &offset.to_string(), &err);
+			self.define_at(&format!("{}.{}.size", name, field), &width.to_string(), &err);
+		}
+		self.define_at(&format!("{}.size", name), &size.to_string(), &err);
+		self.structs.insert(name, Layout {
+			fields:   fields,
+			size:     size,
+			location: err
+		});
+		Ok(())
+	}
+
+	//reserves 'size' bits in the static region and defines 'name' as a pointer to them
+	fn declare_variable(&mut self, name: &str, size: usize, err: &Error) -> Result<usize, AssembleError> {
+		if let Some(previous) = self.variables.get(name) {
+			return Err(err.error(ErrorType::Duplicate{
+				typename: "variable".to_string(),
+				value: name.to_string(),
+				previous: previous.location()
+			}));
+		}
+		if self.statics_locked {
+			return Err(err.error(ErrorType::Generic(format!(
+				"'{}' can't be declared while the program has bits on the stack above its variables", name
+			))));
+		}
+		let offset = self.statics.len();
+		self.statics.resize(offset + size, false);
+		self.variables.insert(name.to_string(), err.clone());
+		self.define_at(name, &format!("[{}:{}]", offset, size), err);
+		Ok(offset)
+	}
+
+	//reserves a variable that starts out holding 'bits'
+	fn declare_data(&mut self, name: &str, bits: &[bool], err: &Error) -> Result<(), AssembleError> {
+		let offset = self.declare_variable(name, bits.len(), err)?;
+		self.statics[offset..].copy_from_slice(bits);
+		Ok(())
+	}
+
+	//the bits of a !data value, which is either a string or a constant followed by ':width'
+	fn evaluate_data(&self, line: &str, tokens: &[Token], err: &Error) -> Result<Vec<bool>, AssembleError> {
+		let colon = tokens.iter().rposition(|token| token.kind == TokenKind::Colon);
+		let (value, width) = match colon {
+			Some(colon) => (&tokens[..colon], Some(self.evaluate_size(line, &tokens[colon + 1..], err)?)),
+			None => (tokens, None)
+		};
+		let text = source_text(value, line);
+		let mut bits = match Value::new(&self.parse_defines(line, value, err)?, err, false)? {
+			Value::Boolvec(bits) => bits,
+			other => match (other.constant(), width) {
+				(Some(ref num), Some(_)) if *num >= gmp::mpz::Mpz::zero() => bignum_to_boolvec(num),
+				(Some(_), None) => return Err(err.error(ErrorType::Generic(format!(
+					"'{}' needs a width, e.g. '{}:64'", text, text
+				)))),
+				_ => return Err(err.error(ErrorType::InvalidValue(text.to_string())))
+			}
+		};
+		if let Some(width) = width {
+			if bits[width.min(bits.len())..].iter().any(|bit| *bit) {
+				return Err(err.error(ErrorType::Generic(format!(
+					"'{}' does not fit into {} bit(s)", text, width
+				))));
+			}
+			bits.resize(width, false);
+		}
+		Ok(bits)
+	}
+
+	fn parse_conditions(&mut self, line: &str, tokens: &[Token], err: &Error) -> Result<bool, AssembleError> {
+		let directive = match (tokens.get(0), tokens.get(1)) {
+			(Some(&Token{kind: TokenKind::Bang, ..}), Some(&Token{kind: TokenKind::Ident(ref name), ..})) => name.as_ref(),
+			_ => return Ok(false)
+		};
+		let args = &tokens[2..];
+		match directive {
+			"if" | "ifdef" | "ifndef" => {
+				let parent = self.is_active();
+				let active = match parent {
+					true => self.evaluate_condition(directive, line, args, err)?,
+					false => false
+				};
+				self.conditions.push(Condition {
+					active:   active,
+					taken:    active,
+					parent:   parent,
+					has_else: false,
+					location: err.clone()
+				});
+			},
+			"elif" | "else" => {
+				let (parent, taken, has_else) = match self.conditions.last() {
+					Some(condition) => (condition.parent, condition.taken, condition.has_else),
+					None => return Err(err.error(ErrorType::Generic(format!(
+						"'!{}' without a matching '!if'", directive
+					))))
+				};
+				if has_else {
+					return Err(err.error(ErrorType::Generic(format!(
+						"'!{}' after '!else'", directive
+					))));
 				}
+				if directive == "else" {
+					err.check_args("macro", directive, args.len(), ArgumentType::Exact(0))?;
+				}
+				let active = match (parent && !taken, directive) {
+					(false, _) => false,
+					(true, "else") => true,
+					(true, _) => self.evaluate_condition(directive, line, args, err)?
+				};
+				let condition = self.conditions.last_mut().unwrap();
+				condition.active = active;
+				condition.taken |= active;
+				condition.has_else = directive == "else";
+			},
+			"endif" => {
+				err.check_args("macro", directive, args.len(), ArgumentType::Exact(0))?;
+				if self.conditions.pop().is_none() {
+					return Err(err.error(ErrorType::Generic(
+						"'!endif' without a matching '!if'".to_string()
+					)));
+				}
+			},
+			_ => return Ok(false)
+		}
+		Ok(true)
+	}
+
+	//replaces every name that has been defined with its value, including names inside of those values
+	fn parse_defines(&self, line: &str, tokens: &[Token], err: &Error) -> Result<String, AssembleError> {
+		self.expand_defines(line, tokens, &mut Vec::new(), err)
+	}
+
+	//'expanding' holds the names whose values are being expanded, to catch names defined as themselves
+	fn expand_defines(&self, line: &str, tokens: &[Token], expanding: &mut Vec<String>, err: &Error)
+	-> Result<String, AssembleError> {
+		let mut ret = String::new();
+		let mut last = match tokens.first() {
+			Some(token) => token.start,
+			None => return Ok(ret)
+		};
+		for token in tokens {
+			ret.push_str(&line[last..token.start]);
+			last = token.end;
+			let value = match token.kind {
+				TokenKind::Ident(ref name) => self.defines.get(name).map(|define| (name, &define.0)),
+				_ => None
+			};
+			let (name, value) = match value {
+				Some(define) => define,
+				None => {
+					ret.push_str(token.text(line));
+					continue;
+				}
+			};
+			if expanding.contains(name) {
+				return Err(err.error(ErrorType::Generic(format!(
+					"'{}' is defined in terms of itself: {} -> {}", name, expanding.join(" -> "), name
+				))));
+			}
+			let value_tokens = match tokenize(value) {
+				Ok(tokens) => tokens,
+				Err(kind) => return Err(err.error(kind))
+			};
+			expanding.push(name.clone());
+			ret.push_str(&self.expand_defines(value, &value_tokens, expanding, err)?);
+			expanding.pop();
+		}
+		Ok(ret)
+	}
+
+	//starts storing lines instead of parsing them, until the end of 'block'
+	fn record(&mut self, block: Block, params: Vec<String>, err: &Error) {
+		self.recording = Some(Recording {
+			block: block,
+			depth: 0,
+			body:  Macro {
+				params:   params,
+				lines:    Vec::new(),
+				location: err.clone()
+			}
+		});
+	}
+
+	//stores a line of the block that is being read, and uses the block once its end is reached
+	fn record_line(&mut self, line: &str, tokens: &[Token], linenum: usize, filename: Option<String>)
+	-> Result<(), AssembleError> {
+		let mut recording = match self.recording.take() {
+			Some(recording) => recording,
+			None => return Ok(())
+		};
+		let directive = match (tokens.get(0), tokens.get(1)) {
+			(Some(&Token{kind: TokenKind::Bang, ..}), Some(token)) => token.text(line),
+			_ => ""
+		};
+		let (start, end) = recording.block.directives();
+		if directive == end && recording.depth == 0 {
+			return match recording.block {
+				Block::Macro(name) => {
+					self.macros.insert(name, recording.body);
+					Ok(())
+				},
+				Block::Rep(count) => self.expand_loop(None, 0, count, &recording.body),
+				Block::For(name, from, to) => self.expand_loop(Some(name), from, to, &recording.body)
+			};
+		}
+		if directive == end {
+			recording.depth -= 1;
+		} else if directive == start {
+			recording.depth += 1;
+		}
+		recording.body.lines.push((line.to_string(), linenum, filename));
+		self.recording = Some(recording);
+		Ok(())
+	}
+
+	//parses the lines of a !rep or !for block once for every value from 'start' up to 'end'
+	fn expand_loop(&mut self, name: Option<String>, start: usize, end: usize, body: &Macro) -> Result<(), AssembleError> {
+		//a count that is obviously too big fails before anything is expanded
+		let lines = end.saturating_sub(start).saturating_mul(body.lines.len());
+		if self.loop_lines.saturating_add(lines) > MAX_LOOP_LINES {
+			return Err(body.location.error(ErrorType::Generic(format!(
+				"loops produce more than {} lines", MAX_LOOP_LINES
+			))));
+		}
+		self.loop_depth += 1;
+		let mut result = Ok(());
+		for i in start..end {
+			let value = i.to_string();
+			result = self.expand_lines(&body.lines, |word| match name {
+				Some(ref name) if name == word => Some(value.clone()),
+				_ => None
+			}, &body.location);
+			if result.is_err() {
+				break;
+			}
+		}
+		self.loop_depth -= 1;
+		if self.loop_depth == 0 {
+			self.loop_lines = 0;
+		}
+		result
+	}
+
+	//parses recorded lines with every name that 'replace' returns a value for substituted
+	fn expand_lines<F>(&mut self, lines: &[(String, usize, Option<String>)], replace: F, err: &Error)
+	-> Result<(), AssembleError> where F: Fn(&str) -> Option<String> {
+		let conditions = self.conditions.len();
+		for &(ref text, linenum, ref file) in lines {
+			let tokens = match tokenize(text) {
+				Ok(tokens) => tokens,
+				Err(kind) => return Err(err.error(kind))
+			};
+			if tokens.is_empty() {
+				continue;
 			}
-			if !did {
-				ret.push_str(&format!("{}", w));
+			let expanded = substitute(text, &tokens, &replace);
+			if let Err(mut e) = self.parse_expanded(&expanded, Some(text), linenum, file.clone()) {
+				e.context.push(("expanded from", err.span().clone()));
+				return Err(e);
 			}
-			match sepchars.pop() {
-				Some(c) => ret.push(c),
-				None => {}
+		}
+		self.check_unterminated(conditions)
+	}
+
+	//parses the lines of a macro with its parameters replaced by 'args'
+	fn expand_macro(&mut self, name: &str, line: &str, args: &[Token], err: &Error) -> Result<(), AssembleError> {
+		let body = match self.macros.get(name) {
+			Some(body) => body.clone(),
+			None => return Ok(())
+		};
+		let args:Vec<String> = split_arguments(args)
+			.iter()
+			.map(|arg| source_text(arg, line).to_string())
+			.collect();
+		err.check_args("macro", name, args.len(), ArgumentType::Exact(body.params.len()))?;
+		if self.macro_depth >= MAX_MACRO_DEPTH {
+			return Err(err.error(ErrorType::Generic(format!(
+				"macro '{}' is nested more than {} levels deep", name, MAX_MACRO_DEPTH
+			))));
+		}
+
+		//every expansion gets its own names for '%%name'
+		self.expansions += 1;
+		let id = self.expansions;
+		self.macro_depth += 1;
+
+		//labels inside of the macro don't change which routine '@' labels after it belong to
+		let scope = self.scope.clone();
+		let result = self.expand_lines(&body.lines, |word| {
+			match word.starts_with("%%") {
+				true => Some(format!("{}${}", &word[2..], id)),
+				false => body.params.iter().position(|param| param == word).map(|i| args[i].clone())
+			}
+		}, err);
+		self.scope = scope;
+		self.macro_depth -= 1;
+		result
+	}
+
+	//returns the number of tokens that make up the label at the start of the line, if there is one
+	fn parse_labels(&mut self, line: &str, tokens: &[Token], err: &Error) -> Result<usize, AssembleError> {
+		let is_label = match tokens[0].kind {
+			TokenKind::Ident(ref name) => name.starts_with('.'),
+			_ => false
+		};
+		if is_label {
+			let name_end = word_end(tokens, 0);
+			let mut name = source_text(&tokens[..name_end], line)[1..].to_string();
+			if name.is_empty() || name == "@" {
+				return Err(err.error(ErrorType::Empty("label".to_string())));
+			}
+			//local labels belong to the global label before them
+			match name.starts_with('@') {
+				true => name = format!("{}{}", self.scope, name),
+				false => self.scope = name.clone()
+			}
+			let key = (self.file_scope(), name);
+			if let Some(previous) = self.label_locations.get(&key) {
+				return Err(err.error(ErrorType::Duplicate{
+					typename: "label".to_string(),
+					value: key.1.clone(),
+					previous: previous.location()
+				}));
+			}
+			self.file_labels.insert(key.clone(), self.code.len());
+			self.label_locations.insert(key, err.clone());
+			return Ok(name_end);
+		}
+		return Ok(0);
+	}
+
+	//the file that is currently being read, labels are only visible inside of it unless exported
+	fn file_scope(&self) -> String {
+		match self.include_stack.last() {
+			Some(&(ref path, _)) => path.to_string_lossy().to_string(),
+			None => String::new()
+		}
+	}
+
+	//makes the labels named by !export visible from every file
+	fn export_labels(&mut self) -> Result<(), AssembleError> {
+		let mut exported:HashMap<String, Error> = HashMap::new();
+		for &(ref file, ref name, ref err) in &self.exports {
+			let key = (file.clone(), name.clone());
+			let pos = match self.file_labels.get(&key) {
+				Some(&pos) => pos,
+				None => return Err(err.error(ErrorType::NonExistent{
+					typename: "label".to_string(),
+					value: name.clone()
+				}))
 			};
-			ret
-		}).collect::<String>()
+			if let Some(previous) = exported.get(name) {
+				if previous.span().file != err.span().file {
+					return Err(err.error(ErrorType::Duplicate{
+						typename: "exported label".to_string(),
+						value: name.clone(),
+						previous: previous.location()
+					}));
+				}
+			}
+			exported.insert(name.clone(), err.clone());
+			self.labels.insert(name.clone(), pos);
+		}
+		Ok(())
 	}
 
-	fn parse_labels(&mut self, line:&String) -> bool {
-		if line.chars().next() == Some('.') {
-			let name = line[1..].to_string();
-			self.labels.insert(name, self.code.len());
-			return true;
+	//resolves the labels used by every instruction, must be called before run
+	pub fn link(&mut self) -> Result<(), AssembleError> {
+		self.check_unterminated(0)?;
+		self.export_labels()?;
+		let labels = &self.labels;
+		let file_labels = &self.file_labels;
+		for ((instruction, span), scope) in self.code.iter_mut().zip(self.spans.iter()).zip(self.scopes.iter()) {
+			//labels in the same file are used before exported ones
+			let resolve = |name: &str| {
+				let local = match name.starts_with('@') {
+					true => format!("{}{}", scope.1, name),
+					false => name.to_string()
+				};
+				file_labels.get(&(scope.0.clone(), local)).or_else(|| labels.get(name)).cloned()
+			};
+			if let Err(kind) = instruction.link(&resolve) {
+				return Err(AssembleError{kind: Box::new(kind), span: span.clone(), context: Vec::new()});
+			}
 		}
-		return false;
+		Ok(())
 	}
 
-	pub fn parse_line(&mut self, linearg: &String, linenum: usize, filename: Option<String>) {
-		//trim and remove comments
-		let mut line:String = linearg.to_string();
-		remove_comments(&mut line, COMMENT_CHAR);
-		line = line.trim().to_string();
+	pub fn parse_line(&mut self, linearg: &String, linenum: usize, filename: Option<String>)
+	-> Result<(), AssembleError> {
+		self.parse_expanded(linearg, None, linenum, filename)
+	}
+
+	//parses a line that came from 'original' in the body of a macro or loop, if there is one
+	fn parse_expanded(&mut self, line: &str, original: Option<&str>, linenum: usize, filename: Option<String>)
+	-> Result<(), AssembleError> {
+		//split into tokens and remove comments
+		let mut tokens = match tokenize(line) {
+			Ok(tokens) => tokens,
+			Err(kind) => {
+				let source = original.unwrap_or(line);
+				let err = Error::new(Span::from_line(source, 0, source.len(), linenum, filename));
+				return Err(err.error(kind));
+			}
+		};
+		strip_comments(&mut tokens);
+		if tokens.is_empty() {
+			return Ok(());//No instruction, skip this line
+		}
 
 		//Create error handler
-		let err = Error::new(line.clone(), linenum, filename);
+		let (start, end) = (tokens[0].start, tokens[tokens.len() - 1].end);
+		if self.recording.is_some() {
+			return self.record_line(line, &tokens, linenum, filename);
+		}
+		if self.pending_struct.is_some() {
+			return self.parse_struct_fields(source_text(&tokens, line));
+		}
+		let err = Error::new(match original {
+			Some(original) => code_span(original, linenum, filename),
+			None => Span::from_line(line, start, end, linenum, filename)
+		});
 
-		//parse strings into 'b10101010' format
-		line = self.parse_strings(&line);
+		//every line produced inside of a loop counts, including the lines of macros it uses
+		if self.loop_depth > 0 {
+			self.loop_lines += 1;
+			if self.loop_lines > MAX_LOOP_LINES {
+				return Err(err.error(ErrorType::Generic(format!(
+					"loops produce more than {} lines", MAX_LOOP_LINES
+				))));
+			}
+		}
+
+		//skip lines inside of !if blocks whose condition doesn't hold
+		if self.parse_conditions(line, &tokens, &err)? || !self.is_active() {
+			return Ok(());
+		}
 
 		//use macros and labels
-		if self.parse_macros(&line, &err) {
-			return;
+		if self.parse_macros(line, &tokens, &err)? {
+			return Ok(());
+		}
+		//a label may be followed by an instruction on the same line
+		let label_end = self.parse_labels(line, &tokens, &err)?;
+		if self.preprocess && label_end > 0 {
+			self.preprocessed.push(format!("{} ; {}", source_text(&tokens[..label_end], line), err.span()));
 		}
-		if self.parse_labels(&line) {
-			return;
+		let tokens = &tokens[label_end..];
+		if tokens.is_empty() {
+			return Ok(());
+		}
+		if let TokenKind::Ident(ref name) = tokens[0].kind {
+			if self.macros.contains_key(name) {
+				return self.expand_macro(name, line, &tokens[1..], &err);
+			}
 		}
 
-		line = self.parse_defines(&line);
+		let expanded = self.parse_defines(line, &tokens, &err)?;
+		let mut tokens = match tokenize(&expanded) {
+			Ok(tokens) => tokens,
+			Err(kind) => return Err(err.error(kind))
+		};
+		strip_comments(&mut tokens);
+		if tokens.is_empty() {
+			return Ok(());
+		}
+		if self.preprocess {
+			self.preprocessed.push(format!("\t{} ; {}", lower_literals(&tokens, &expanded), err.span()));
+		}
 
 		//parse name and arguments
-		let mut arg_string:String = String::new();
-		let name:String = match line.split_whitespace().next(){
-			Some(val) => {val.to_string()},
-			None => return,//No instruction, skip this line
+		let name:String = tokens[0].text(&expanded).to_string();
+		let arg_vec:Vec<&str> = split_arguments(&tokens[1..])
+			.iter()
+			.map(|arg| source_text(arg, &expanded))
+			.collect();
+
+		self.parse_args(&name, &arg_vec, &err)
+	}
+
+	pub fn load_text(&mut self, code: &str) -> Result<(), AssembleError> {
+		let conditions = self.conditions.len();
+		for (i, line) in code.lines().enumerate() {
+			if i == 0 && is_shebang(line) {
+				continue;
+			}
+			for statement in split_statements(line) {
+				self.parse_line(&statement.to_string(), i + 1, None)?;
+			}
+		}
+		self.check_unterminated(conditions)
+	}
+
+	pub fn load_file(&mut self, file_name: &str) -> Result<(), AssembleError> {
+		let err = Error::new(Span::new("".to_string(), 0, (0, 0), Some(file_name.to_string())));
+		self.read_file(Path::new(file_name), &err)
+	}
+
+	//adds a directory that !include searches when a file isn't next to the file including it
+	pub fn add_include_path(&mut self, path: &str) {
+		self.include_paths.push(PathBuf::from(path));
+	}
+
+	//finds the file an !include refers to, relative to the including file and then the include paths
+	fn resolve_include(&self, file_name: &str, err: &Error) -> Result<PathBuf, AssembleError> {
+		let including_dir = match err.span().file {
+			Some(ref file) => Path::new(file).parent().map(|dir| dir.to_path_buf()).unwrap_or_default(),
+			None => PathBuf::new()
 		};
-		match line.find(name.as_str()){
-			Some(pos) => {arg_string = line[(pos+name.len())..].trim().to_string();}
-			None => {},
-		}
-		let arg_vec:Vec<&str> = arg_string
-			.split(ARGUMENT_CHAR)
-			.map(|val|val.trim())
-			.filter(|val|val.trim() != "")
+		let candidates = Some(&including_dir).into_iter().chain(self.include_paths.iter());
+		for dir in candidates {
+			let path = dir.join(file_name);
+			if path.is_file() {
+				return Ok(path);
+			}
+		}
+		Err(err.error(ErrorType::FileError{
+			file: file_name.to_string(),
+			reason: "not found next to the including file or in any include path".to_string()
+		}))
+	}
+
+	fn include_file(&mut self, file_name: &str, err: &Error) -> Result<(), AssembleError> {
+		let path = self.resolve_include(file_name, err)?;
+		self.read_file(&path, err)
+	}
+
+	//reads every line of a file into the assembler, errors are reported at 'err'
+	fn read_file(&mut self, path: &Path, err: &Error) -> Result<(), AssembleError> {
+		let file_name = path.to_string_lossy().to_string();
+		let key = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+		if self.once_files.contains(&key) {
+			return Ok(());
+		}
+		if self.include_stack.iter().any(|&(ref included, _)| *included == key) {
+			let chain:Vec<&str> = self.include_stack.iter()
+				.skip_while(|&&(ref included, _)| *included != key)
+				.map(|&(_, ref name)| name.as_ref())
+				.collect();
+			return Err(err.error(ErrorType::Generic(format!(
+				"include cycle {} -> {}", chain.join(" -> "), file_name
+			))));
+		}
+		let file = match File::open(path) {
+			Ok(file) => file,
+			Err(e) => return Err(err.error(ErrorType::FileError{
+				file: file_name.to_string(),
+				reason: format!("{}", e)
+			}))
+		};
+		let buffer = BufReader::new(&file);
+		let conditions = self.conditions.len();
+		self.include_stack.push((key, file_name.clone()));
+		let scope = ::std::mem::replace(&mut self.scope, String::new());
+		let mut result = Ok(());
+		let mut linenum = 0;
+		for line in buffer.lines() {
+			let l:String = match line {
+				Ok(l) => l,
+				Err(e) => {
+					result = Err(err.error(ErrorType::FileError{
+						file: file_name.clone(),
+						reason: format!("{}", e)
+					}));
+					break;
+				}
+			};
+			linenum += 1;
+			if linenum == 1 && is_shebang(&l) {
+				continue;
+			}
+			result = self.parse_line(&l, linenum, Some(file_name.clone()));
+			if result.is_err() {
+				break;
+			}
+		}
+		self.include_stack.pop();
+		self.scope = scope;
+		result = result.and_then(|_| self.check_unterminated(conditions));
+
+		//errors inside of an included file also show where it was included
+		if let Err(ref mut e) = result {
+			if err.span().line > 0 {
+				e.context.push(("included from", err.span().clone()));
+			}
+		}
+		result
+	}
+
+	//macros, structs and !if blocks must end in the same file they start in
+	fn check_unterminated(&self, conditions: usize) -> Result<(), AssembleError> {
+		if let Some((ref name, _, ref location)) = self.pending_struct {
+			return Err(location.error(ErrorType::Generic(format!(
+				"struct '{}' is missing its closing '}}'", name
+			))));
+		}
+		if let Some(ref recording) = self.recording {
+			let (start, end) = recording.block.directives();
+			return Err(recording.body.location.error(ErrorType::Generic(match recording.block {
+				Block::Macro(ref name) => format!("macro '{}' is missing its '!{}'", name, end),
+				_ => format!("'!{}' is missing its '!{}'", start, end)
+			})));
+		}
+		match self.conditions.get(conditions) {
+			Some(condition) => Err(condition.location.error(ErrorType::Generic(
+				"'!if' is missing its '!endif'".to_string()
+			))),
+			None => Ok(())
+		}
+	}
+
+	//the source of the program after every macro, include, define and condition has been used,
+	//with the location each line came from in a comment after it
+	pub fn preprocessed(&self) -> String {
+		let mut ret = String::new();
+		if !self.statics.is_empty() {
+			ret.push_str(&format!("!data __statics {}'h{}\n",
+				self.statics.len(),
+				boolvec_to_bignum(&self.statics).to_str_radix(16)
+			));
+		}
+		for line in &self.preprocessed {
+			ret.push_str(line);
+			ret.push('\n');
+		}
+		ret
+	}
+
+	//a listing of every compiled instruction with its index, the labels that point to it
+	//and the location it came from
+	pub fn disassemble(&self) -> String {
+		let mut labels:Vec<(usize, &str)> = self.file_labels.iter()
+			.map(|(key, pos)| (*pos, key.1.as_ref()))
 			.collect();
+		labels.sort();
+		let mut ret = String::new();
+		let mut next_label = 0;
+		for (i, span) in self.spans.iter().enumerate() {
+			while next_label < labels.len() && labels[next_label].0 <= i {
+				ret.push_str(&format!(".{}\n", labels[next_label].1));
+				next_label += 1;
+			}
+			ret.push_str(&format!("{:>6}  {} ; {}\n", i, span.text, span));
+		}
+		for &(_, name) in &labels[next_label..] {
+			ret.push_str(&format!(".{}\n", name));
+		}
+		ret
+	}
+
+	//removes every instruction from 'len' onwards and any block that is still open,
+	//e.g. after lines that could not be assembled
+	pub fn truncate(&mut self, len: usize) {
+		self.code.truncate(len);
+		self.spans.truncate(len);
+		self.scopes.truncate(len);
+		self.conditions.clear();
+		self.recording = None;
+		self.pending_struct = None;
+	}
+
+	//whether a !macro, !rep, !for, !if or !struct has been started and not ended yet
+	pub fn in_block(&self) -> bool {
+		!self.conditions.is_empty() || self.recording.is_some() || self.pending_struct.is_some()
+	}
+
+	//the bits that are pushed before the first instruction runs
+	pub fn statics(&self) -> &[bool] {
+		&self.statics
+	}
 
-		self.parse_args(&name, &arg_vec, &err);
+	//the number of compiled instructions
+	pub fn len(&self) -> usize {
+		self.code.len()
 	}
 
-	pub fn run(&mut self, env: &mut Environment) {
+	//the source location an instruction was compiled from
+	pub fn span(&self, index: usize) -> Option<&Span> {
+		self.spans.get(index)
+	}
+
+	//runs until the end of the program or until it halts, returning its exit status
+	pub fn run(&mut self, env: &mut Environment) -> Result<i32, RuntimeError> {
+		//variables that were declared since the last run also need space, e.g. in the repl
+		if env.static_bits < self.statics.len() {
+			env.push_boolvec(&self.statics[env.static_bits..]);
+			env.static_bits = self.statics.len();
+		}
+		env.exit_status = None;
+		let result = self.execute(env);
+
+		//new variables are pushed at the end of the stack, which is only where their offsets point to
+		//while the program hasn't left any bits of its own there
+		self.statics_locked = env.stack_len() != self.statics.len();
+		result
+	}
+
+	fn execute(&mut self, env: &mut Environment) -> Result<i32, RuntimeError> {
 		let len = self.code.len();
 		while env.instruction < len {
-			let ins = &self.code[env.instruction];
+			let index = env.instruction;
+			if env.step_limit.map_or(false, |limit| env.steps >= limit) {
+				return Err(RuntimeError{
+					kind: RuntimeErrorType::StepLimit(env.steps),
+					instruction: index,
+					span: self.spans[index].clone()
+				});
+			}
 			env.instruction += 1;
-			ins.exec(env, self);
+			env.steps += 1;
+			if self.trace {
+				let span = &self.spans[index];
+				eprintln!("[{}] {}: {}", index, span, span.text);
+			}
+			if let Err(kind) = self.code[index].exec(env, self) {
+				return Err(RuntimeError{
+					kind: kind,
+					instruction: index,
+					span: self.spans[index].clone()
+				});
+			}
+			if let Some(status) = env.exit_status {
+				return Ok(status);
+			}
+		}
+		Ok(0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assemble(source: &str) -> Result<Assembler, String> {
+		let mut asm = Assembler::new(false);
+		match asm.load_text(source).and_then(|_| asm.link()) {
+			Ok(_) => Ok(asm),
+			Err(e) => Err(format!("{}", e))
+		}
+	}
+
+	//writes 'files' into a new directory for the test 'name' and assembles the first of them
+	fn assemble_files(name: &str, files: &[(&str, &str)], include_paths: &[&str]) -> Result<Assembler, String> {
+		let dir = env::temp_dir().join(format!("bit-asm-{}", name));
+		fs::remove_dir_all(&dir).ok();
+		for &(file, text) in files {
+			let path = dir.join(file);
+			fs::create_dir_all(path.parent().unwrap()).unwrap();
+			fs::write(&path, text).unwrap();
+		}
+		let mut asm = Assembler::new(false);
+		for path in include_paths {
+			asm.add_include_path(&dir.join(path).to_string_lossy());
+		}
+		let main = dir.join(files[0].0).to_string_lossy().to_string();
+		match asm.load_file(&main).and_then(|_| asm.link()) {
+			Ok(_) => Ok(asm),
+			Err(e) => Err(format!("{}", e))
+		}
+	}
+
+	//runs an assembled program and returns the stack it leaves behind
+	fn run_assembled(asm: Result<Assembler, String>) -> Environment {
+		let mut asm = match asm {
+			Ok(asm) => asm,
+			Err(e) => panic!("the program could not be assembled: {}", e)
+		};
+		let mut env = Environment::new();
+		if let Err(e) = asm.run(&mut env) {
+			panic!("the program stopped: {}", e);
+		}
+		env
+	}
+
+	fn run(source: &str) -> Environment {
+		run_assembled(assemble(source))
+	}
+
+	//the error message of a program that can't be assembled
+	fn error(source: &str) -> String {
+		match assemble(source) {
+			Ok(_) => panic!("the program was assembled without errors"),
+			Err(e) => e
+		}
+	}
+
+	fn number(env: &Environment, pos: usize, len: usize) -> usize {
+		match env.slice(pos, pos + len) {
+			Ok(bits) => boolvec_to_usize(bits),
+			Err(e) => panic!("[{}:{}] is not on the stack: {}", pos, len, e)
+		}
+	}
+
+	#[test]
+	fn substitutes_macro_parameters() {
+		let env = run("
+			!var a 8
+			!var b 8
+			!macro set dst, value
+				mov dst, value
+			!endmacro
+			set a, 5
+			set b, 2 * 3 + 1
+		");
+		assert_eq!(number(&env, 0, 8), 5);
+		assert_eq!(number(&env, 8, 8), 7);
+	}
+
+	#[test]
+	fn gives_local_names_to_each_expansion() {
+		let env = run("
+			!var n 8
+			!var total 8
+			!macro count to
+				mov n, 0
+			.%%loop
+				add n, n, 1
+				add total, total, 1
+				jne n, to, %%loop
+			!endmacro
+			count 3
+			count 5
+		");
+		assert_eq!(number(&env, 0, 8), 5);
+		assert_eq!(number(&env, 8, 8), 8);
+	}
+
+	#[test]
+	fn expands_macros_inside_of_macros() {
+		let env = run("
+			!var n 8
+			!macro inc x
+				add x, x, 1
+			!endmacro
+			!macro inc2 x
+				inc x
+				inc x
+			!endmacro
+			inc2 n
+			inc2 n
+			inc n
+		");
+		assert_eq!(number(&env, 0, 8), 5);
+	}
+
+	#[test]
+	fn limits_macro_depth() {
+		let e = error("
+			!macro forever
+				forever
+			!endmacro
+			forever
+		");
+		assert!(e.contains("nested more than 64 levels deep"), "{}", e);
+	}
+
+	#[test]
+	fn rejects_duplicate_and_unterminated_macros() {
+		let e = error("
+			!macro m
+			!endmacro
+			!macro m
+			!endmacro
+		");
+		assert!(e.contains("macro 'm' was already defined"), "{}", e);
+		let e = error("
+			!macro m
+				push 8
+		");
+		assert!(e.contains("macro 'm' is missing its '!endmacro'"), "{}", e);
+		let e = error("!endmacro");
+		assert!(e.contains("'!endmacro' without a matching '!macro'"), "{}", e);
+	}
+
+	#[test]
+	fn includes_files_next_to_the_including_file() {
+		let env = run_assembled(assemble_files("include-relative", &[
+			("main.asm", "!var n 8\n!include \"lib/a.asm\"\n"),
+			("lib/a.asm", "add n, n, 1\n!include \"b.asm\"\n"),
+			("lib/b.asm", "add n, n, 2\n")
+		], &[]));
+		assert_eq!(number(&env, 0, 8), 3);
+	}
+
+	#[test]
+	fn searches_include_paths() {
+		let files = [
+			("main.asm", "!var n 8\n!include \"util.asm\"\n"),
+			("vendor/util.asm", "mov n, 4\n")
+		];
+		let env = run_assembled(assemble_files("include-path", &files, &["vendor"]));
+		assert_eq!(number(&env, 0, 8), 4);
+		match assemble_files("include-path-missing", &files, &[]) {
+			Ok(_) => panic!("'util.asm' was found without an include path"),
+			Err(e) => assert!(e.contains("not found"), "{}", e)
+		}
+	}
+
+	#[test]
+	fn reads_once_files_a_single_time() {
+		let env = run_assembled(assemble_files("include-once", &[
+			("main.asm", "!var n 8\n!include \"inc.asm\"\n!include \"inc.asm\"\n!include \"twice.asm\"\n!include \"twice.asm\"\n"),
+			("inc.asm", "!once\nadd n, n, 1\n"),
+			("twice.asm", "add n, n, 10\n")
+		], &[]));
+		assert_eq!(number(&env, 0, 8), 21);
+	}
+
+	#[test]
+	fn rejects_include_cycles() {
+		match assemble_files("include-cycle", &[
+			("a.asm", "!include \"b.asm\"\n"),
+			("b.asm", "!include \"a.asm\"\n")
+		], &[]) {
+			Ok(_) => panic!("the include cycle was assembled"),
+			Err(e) => {
+				assert!(e.contains("include cycle"), "{}", e);
+				assert!(e.contains("included from"), "{}", e);
+			}
+		}
+	}
+
+	#[test]
+	fn scopes_local_labels_to_the_last_routine() {
+		let env = run("
+			!var a 8
+			!var b 8
+			jmp main
+			.first
+				mov a, 0
+			.@loop	add a, a, 1
+				jne a, 3, @loop
+				ret
+			.second
+				mov b, 0
+			.@loop	add b, b, 1
+				jne b, 4, @loop
+				ret
+			.main
+				call first
+				call second
+		");
+		assert_eq!(number(&env, 0, 8), 3);
+		assert_eq!(number(&env, 8, 8), 4);
+		let e = error(".first\n.@loop\n.second\njmp @loop");
+		assert!(e.contains("no such label of name '@loop'"), "{}", e);
+	}
+
+	#[test]
+	fn keeps_the_scope_after_a_macro() {
+		let env = run("
+			!var n 8
+			!macro m
+			.%%inner
+				add n, n, 1
+			!endmacro
+			jmp routine@end
+			.routine
+				m
+			.@end	add n, n, 10
+		");
+		assert_eq!(number(&env, 0, 8), 10);
+	}
+
+	#[test]
+	fn keeps_labels_inside_of_their_file() {
+		let hidden = [
+			("main.asm", "!include \"lib.asm\"\n.loop\ncall helper\n"),
+			("lib.asm", "jmp end\n.loop\n.helper\nret\n.end\n")
+		];
+		match assemble_files("labels-hidden", &hidden, &[]) {
+			Ok(_) => panic!("a label of another file was used without !export"),
+			Err(e) => assert!(e.contains("no such label of name 'helper'"), "{}", e)
+		}
+		let env = run_assembled(assemble_files("labels-exported", &[
+			("main.asm", "!var n 8\n!include \"lib.asm\"\n.loop\ncall helper\n"),
+			("lib.asm", "!export helper\njmp end\n.loop\n.helper\nadd n, n, 1\nret\n.end\n")
+		], &[]));
+		assert_eq!(number(&env, 0, 8), 1);
+	}
+
+	#[test]
+	fn rejects_colliding_exports() {
+		match assemble_files("labels-collision", &[
+			("main.asm", "!include \"a.asm\"\n!include \"b.asm\"\n"),
+			("a.asm", "!export helper\n.helper\n"),
+			("b.asm", "!export helper\n.helper\n")
+		], &[]) {
+			Ok(_) => panic!("two files exported 'helper'"),
+			Err(e) => assert!(e.contains("exported label 'helper' was already defined"), "{}", e)
+		}
+	}
+
+	#[test]
+	fn assembles_the_first_true_branch() {
+		let env = run("
+			!var n 8
+			!define MODE 2
+			!if MODE == 1
+				mov n, 1
+			!elif MODE == 2
+				mov n, 2
+			!else
+				mov n, 3
+			!endif
+		");
+		assert_eq!(number(&env, 0, 8), 2);
+	}
+
+	#[test]
+	fn defines_names_without_a_value_as_1() {
+		let env = run("
+			!var a 8
+			!var b 8
+			!define DEBUG
+			!ifdef DEBUG
+				mov a, DEBUG
+			!endif
+			!ifndef RELEASE
+				mov b, 5
+			!endif
+		");
+		assert_eq!(number(&env, 0, 8), 1);
+		assert_eq!(number(&env, 8, 8), 5);
+		assert!(error("!define").contains("expected at least 1 argument(s)"));
+	}
+
+	#[test]
+	fn rejects_unbalanced_conditions() {
+		assert!(error("!if 1\npush 8").contains("'!if' is missing its '!endif'"));
+		assert!(error("!endif").contains("'!endif' without a matching '!if'"));
+		assert!(error("!if 1\n!else\n!else\n!endif").contains("'!else' after '!else'"));
+	}
+
+	#[test]
+	fn lays_out_variables_one_after_another() {
+		let env = run("
+			!var a 8
+			!var wide 8 * 2
+			!array list 4, 1 + 2
+			mov a, <wide
+			mov wide, <list
+			mov [<list + 2 * list.width:list.width], list.count
+		");
+		assert_eq!(env.stack_len(), 8 + 16 + 12);
+		assert_eq!(number(&env, 0, 8), 8);
+		assert_eq!(number(&env, 8, 16), 24);
+		assert_eq!(number(&env, 24 + 8, 4), 3);
+	}
+
+	#[test]
+	fn rejects_invalid_variables() {
+		assert!(error("!var a 8\n!var a 8").contains("variable 'a' was already defined"));
+		assert!(error("!var a").contains("expected 2 argument(s)"));
+		assert!(error("!array a 8 4").contains("expected 3 argument(s)"));
+		assert!(error("!var a 0 - 1").contains("InvalidValue"));
+	}
+
+	#[test]
+	fn lays_out_struct_fields() {
+		let env = run("
+			!struct Flags { ack: 1, syn: 1 }
+			!struct Header {
+				version: 4
+				length: 12
+				flags: Flags
+			}
+			!var sizes 64
+			!var header Header
+			mov [0:8], Header.size
+			mov [8:8], Header.flags.syn
+			mov [16:8], Header.length.size
+			mov [24:8], Flags.syn
+			mov [header.length], 300
+			mov [header.flags.syn], 1
+			push Header.size
+			mov [<[-0] - Header.size + Header.version:Header.version.size], 9
+		");
+		assert_eq!(number(&env, 0, 8), 18);
+		assert_eq!(number(&env, 8, 8), 17);
+		assert_eq!(number(&env, 16, 8), 12);
+		assert_eq!(number(&env, 24, 8), 1);
+		assert_eq!(number(&env, 64 + 4, 12), 300);
+		assert_eq!(number(&env, 64 + 17, 1), 1);
+		assert_eq!(number(&env, 64 + 18, 4), 9);
+	}
+
+	#[test]
+	fn rejects_invalid_structs() {
+		assert!(error("!struct A { a: 1, a: 2 }").contains("more than one field named 'a'"));
+		assert!(error("!struct A { a 1 }").contains("is not a field"));
+		assert!(error("!struct A {\na: 1").contains("struct 'A' is missing its closing '}'"));
+		assert!(error("!struct A { a: 1 }\n!struct A { b: 1 }").contains("struct 'A' was already defined"));
+	}
+
+	#[test]
+	fn places_data_before_the_program() {
+		let env = run_assembled(assemble_files("data", &[
+			("main.asm", "!data text \"hi\"\n!data mask 0xFF00:16\n!data byte 8'h7F\n!zero gap 4\n!incbin file \"file.bin\"\n"),
+			("file.bin", "ok")
+		], &[]));
+		assert_eq!(env.stack_len(), 16 + 16 + 8 + 4 + 16);
+		assert!(env.slice(0, 16).ok() == Some(&str_to_boolvec("hi")[..]));
+		assert_eq!(number(&env, 16, 16), 0xFF00);
+		assert_eq!(number(&env, 32, 8), 0x7F);
+		assert_eq!(number(&env, 40, 4), 0);
+		assert!(env.slice(44, 60).ok() == Some(&bytes_to_boolvec(b"ok")[..]));
+	}
+
+	#[test]
+	fn rejects_invalid_data() {
+		assert!(error("!data a 300:8").contains("'300' does not fit into 8 bit(s)"));
+		assert!(error("!data a 5").contains("'5' needs a width"));
+		assert!(error("!incbin a \"missing.bin\"").contains("FileError"));
+	}
+
+	#[test]
+	fn checks_assertions_while_assembling() {
+		assert!(assemble("!define WIDTH 64\n!assert WIDTH <= 64, \"too wide\"").is_ok());
+		let e = error("!define WIDTH 128\n!assert WIDTH <= 64, \"too wide\"");
+		assert!(e.contains("AssertionError: too wide"), "{}", e);
+		let e = error("!ifndef WIDTH\n!error \"WIDTH must be defined\"\n!endif");
+		assert!(e.contains("UserError: WIDTH must be defined"), "{}", e);
+		assert!(error("!assert 1 + [0:8]").contains("not a constant expression"));
+	}
+
+	#[test]
+	fn collects_warnings_and_printed_values() {
+		let asm = match assemble("!warning \"careful\"\n!define WIDTH 16\n!print \"using\", WIDTH * 2, \"bits\"") {
+			Ok(asm) => asm,
+			Err(e) => panic!("the program could not be assembled: {}", e)
+		};
+		let messages:Vec<String> = asm.diagnostics.iter().map(|d| format!("{}", d.kind)).collect();
+		assert_eq!(messages.len(), 2);
+		assert!(messages[0].contains("careful"), "{}", messages[0]);
+		assert!(messages[1].contains("using") && messages[1].contains("32") && messages[1].contains("bits"), "{}", messages[1]);
+	}
+
+	#[test]
+	fn repeats_and_substitutes_loops() {
+		let env = run("
+			!var n 8
+			!array list 8, 4
+			!rep 3
+				add n, n, 2
+			!endrep
+			!for i in 0..4
+				mov [<list + i * 8:8], i * i
+			!endfor
+		");
+		assert_eq!(number(&env, 0, 8), 6);
+		assert_eq!(number(&env, 8 + 3 * 8, 8), 9);
+	}
+
+	#[test]
+	fn nests_loops() {
+		let env = run("
+			!var n 16
+			!for i in 0..3
+				!for j in 1..4
+					add n, n, i * j
+				!endfor
+			!endfor
+			!rep 0
+				mov n, 0
+			!endrep
+		");
+		assert_eq!(number(&env, 0, 16), 18);
+	}
+
+	#[test]
+	fn limits_the_lines_loops_produce() {
+		let e = error("!rep 100000\npush 1\n!endrep");
+		assert!(e.contains("loops produce more than 65536 lines"), "{}", e);
+		let e = error("!macro big\n!rep 200\npush 1\n!endrep\n!endmacro\n!rep 1000\nbig\n!endrep");
+		assert!(e.contains("loops produce more than 65536 lines"), "{}", e);
+		assert!(error("!for i 0..2\n!endfor").contains("expected '!for [name] in [start]..[end]'"));
+	}
+
+	#[test]
+	fn expands_names_inside_of_defines() {
+		let env = run("
+			!var n 8
+			!define BASE 4
+			!define TOTAL BASE * 2
+			!define BASE 5
+			mov n, TOTAL
+		");
+		assert_eq!(number(&env, 0, 8), 10);
+		let e = error("!define a b\n!define b a\npush a");
+		assert!(e.contains("'a' is defined in terms of itself: a -> b -> a"), "{}", e);
+	}
+
+	#[test]
+	fn warns_about_redefined_names() {
+		let asm = match assemble("!define A 1\n!define A 2") {
+			Ok(asm) => asm,
+			Err(e) => panic!("the program could not be assembled: {}", e)
+		};
+		assert_eq!(asm.diagnostics.len(), 1);
+		let warning = format!("{}", asm.diagnostics[0]);
+		assert!(warning.contains("'A' was already defined on line 1, it now has the value '2'"), "{}", warning);
+	}
+
+	#[test]
+	fn forgets_undefined_names() {
+		let env = run("
+			!var n 8
+			!define A 1
+			!undef A
+			!ifndef A
+				mov n, 7
+			!endif
+		");
+		assert_eq!(number(&env, 0, 8), 7);
+		assert!(error("!define A 1\n!undef A\npush A").contains("InvalidValue: A"));
+	}
+
+	#[test]
+	fn writes_out_the_preprocessed_program() {
+		let source = "
+			!var n 16
+			!define STEP 3
+			!macro bump x
+				add x, x, STEP
+			!endmacro
+			.start
+			!rep 2
+				bump n
+			!endrep
+			!data text \"hi\"
+			ext print, text
+		";
+		let mut asm = Assembler::new(false);
+		asm.preprocess = true;
+		if let Err(e) = asm.load_text(source) {
+			panic!("the program could not be assembled: {}", e);
+		}
+		let output = asm.preprocessed();
+		let lines:Vec<&str> = output.lines().collect();
+		assert_eq!(lines[0], "!data __statics 32'h96160000");
+		assert_eq!(lines[1], ".start ; <text>:7:4-10");
+		assert_eq!(lines[2], "\tadd [0:16], [0:16], 3 ; <text>:5:5-19");
+		assert_eq!(lines[3], lines[2]);
+		assert_eq!(lines[4], "\text print, [16:16] ; <text>:12:4-19");
+
+		//the output can be assembled again and does the same thing
+		let env = run(&output);
+		assert_eq!(number(&env, 0, 16), 6);
+	}
+
+	//runs a program with 'args' and returns its exit status along with the stack
+	fn run_with_args(source: &str, args: &[&str]) -> (i32, Environment) {
+		let mut asm = match assemble(source) {
+			Ok(asm) => asm,
+			Err(e) => panic!("the program could not be assembled: {}", e)
+		};
+		let mut env = Environment::new();
+		env.args = args.iter().map(|arg| arg.to_string()).collect();
+		match asm.run(&mut env) {
+			Ok(status) => (status, env),
+			Err(e) => panic!("the program stopped: {}", e)
+		}
+	}
+
+	#[test]
+	fn halts_with_a_status() {
+		let (status, env) = run_with_args("!var n 8\nmov n, 1\nhalt 3\nmov n, 2", &[]);
+		assert_eq!(status, 3);
+		assert_eq!(env.exit_status, Some(3));
+		assert_eq!(number(&env, 0, 8), 1);
+		assert_eq!(run_with_args("halt 0x1FF", &[]).0, 0xFF);
+		assert_eq!(run_with_args("halt", &[]).0, 0);
+		assert_eq!(run_with_args("ext exit, 8'd42\nhalt 1", &[]).0, 42);
+
+		let (status, env) = run_with_args("push 8", &[]);
+		assert_eq!(status, 0);
+		assert_eq!(env.exit_status, None);
+	}
+
+	#[test]
+	fn gives_arguments_to_the_program() {
+		let source = "
+			!var count 8
+			!var len 8
+			!var ok 1
+			!var missing 1
+			!var text 16
+			!var index 8
+			ext argc, count
+			mov len, 1
+			ext arglen, len
+			ext valid, ok
+			mov text, 1
+			ext arg, text
+			mov index, 5
+			ext arglen, index
+			ext valid, missing
+		";
+		let (_, env) = run_with_args(source, &["one", "hi"]);
+		assert_eq!(number(&env, 0, 8), 2);
+		assert_eq!(number(&env, 8, 8), 16);
+		assert_eq!(number(&env, 16, 1), 1);
+		assert_eq!(number(&env, 17, 1), 0);
+		assert!(env.slice(18, 34).ok() == Some(&str_to_boolvec("hi")[..]));
+	}
+
+	#[test]
+	fn reads_environment_variables() {
+		env::set_var("BIT_ASM_TEST_GETENV", "ok");
+		let source = "
+			!var text 16
+			!var len 8
+			!var set 1
+			!var unset 1
+			ext getenv, \"BIT_ASM_TEST_GETENV\"
+			ext valid, set
+			ext inputlen, len
+			ext input, text
+			ext getenv, \"BIT_ASM_TEST_UNSET\"
+			ext valid, unset
+		";
+		let (_, env) = run_with_args(source, &[]);
+		assert!(env.slice(0, 16).ok() == Some(&str_to_boolvec("ok")[..]));
+		assert_eq!(number(&env, 16, 8), 16);
+		assert_eq!(number(&env, 24, 1), 1);
+		assert_eq!(number(&env, 25, 1), 0);
+	}
+
+	#[test]
+	fn points_at_the_macro_line_and_its_use() {
+		let e = error("!macro m a\n\tmov a, 1\n\tnop a\n!endmacro\npush 8\n  m [0:8]");
+		assert!(e.starts_with("Error on line 3,"), "{}", e);
+		assert!(e.contains(">>> nop a\n    expanded from line 6"), "{}", e);
+	}
+
+	#[test]
+	fn pushes_variables_declared_between_runs() {
+		let mut asm = Assembler::new(false);
+		let mut env = Environment::new();
+		let step = |asm: &mut Assembler, env: &mut Environment, line: &str| {
+			match asm.parse_line(&line.to_string(), 1, None).and_then(|_| asm.link()) {
+				Ok(_) => asm.run(env).map(|_| ()).map_err(|e| format!("{}", e)),
+				Err(e) => Err(format!("{}", e))
+			}
+		};
+		assert!(step(&mut asm, &mut env, "!var a 8").is_ok());
+		assert!(step(&mut asm, &mut env, "mov a, 3").is_ok());
+		assert!(step(&mut asm, &mut env, "!var b 8").is_ok());
+		assert!(step(&mut asm, &mut env, "mov b, 4").is_ok());
+		assert_eq!(number(&env, 0, 8), 3);
+		assert_eq!(number(&env, 8, 8), 4);
+
+		//bits pushed by the program are where the next variable would go
+		assert!(step(&mut asm, &mut env, "push 8").is_ok());
+		match step(&mut asm, &mut env, "!var c 8") {
+			Ok(_) => panic!("'c' was declared on top of pushed bits"),
+			Err(e) => assert!(e.contains("can't be declared"), "{}", e)
 		}
+		assert!(step(&mut asm, &mut env, "pop 8").is_ok());
+		assert!(step(&mut asm, &mut env, "!var c 8").is_ok());
+		assert_eq!(env.stack_len(), 24);
 	}
 }
@@ -1,7 +1,7 @@
 extern crate gmp;
 extern crate time;
 use super::util::*;
-use super::assembler::Assembler;
+use super::error::RuntimeErrorType;
 use std::mem::size_of;
 
 pub struct Environment {
@@ -10,7 +10,16 @@ pub struct Environment {
 	pub instruction: usize,
 	pub validity: bool,
 	pub input_string: String,
-	pub randstate: gmp::rand::RandState
+	pub randstate: gmp::rand::RandState,
+	//the arguments given to the program, e.g. after '--' on the command line
+	pub args: Vec<String>,
+	//how many bits of the assembler's variables and data have been pushed onto the stack
+	pub static_bits: usize,
+	//the number of instructions run so far, and how many may be run before the program is stopped
+	pub steps: usize,
+	pub step_limit: Option<usize>,
+	//set by 'halt' and 'ext exit', stops the program once the current instruction is done
+	pub exit_status: Option<i32>
 }
 
 #[allow(dead_code)]
@@ -22,7 +31,12 @@ impl Environment {
 			instruction: 0,
 			validity: true,
 			input_string: "".to_string(),
-			randstate: gmp::rand::RandState::new()
+			randstate: gmp::rand::RandState::new(),
+			args: Vec::new(),
+			static_bits: 0,
+			steps: 0,
+			step_limit: None,
+			exit_status: None
 		};
 		ret.randstate.seed_ui(time::get_time().sec as u64);
 		ret
@@ -37,61 +51,93 @@ impl Environment {
 		self.stack.resize(len + bits, value);
 	}
 
-	pub fn pop(&mut self, bits: usize) {
+	//pushes 'bits' onto the stack, with the first one ending up lowest
+	pub fn push_boolvec(&mut self, bits: &[bool]) {
+		self.stack.extend_from_slice(bits);
+	}
+
+	pub fn pop(&mut self, bits: usize) -> Result<(), RuntimeErrorType> {
 		let len = self.stack.len();
+		if bits > len {
+			return Err(RuntimeErrorType::StackUnderflow{bits: bits, stack: len});
+		}
 		self.stack.truncate(len - bits);
+		Ok(())
+	}
+
+	//returns an error if the bits from 'pos' to 'pos + len' are not on the stack
+	pub fn check_bounds(&self, pos: usize, len: usize) -> Result<(), RuntimeErrorType> {
+		match pos.checked_add(len) {
+			Some(end) if end <= self.stack.len() => Ok(()),
+			_ => Err(RuntimeErrorType::OutOfBounds{pos: pos, len: len, stack: self.stack.len()})
+		}
 	}
 
-	pub fn slice(&self, start: usize, end: usize) -> &[bool] {
-		&self.stack[start..end]
+	pub fn slice(&self, start: usize, end: usize) -> Result<&[bool], RuntimeErrorType> {
+		self.check_bounds(start, end.saturating_sub(start))?;
+		Ok(&self.stack[start..end])
 	}
 
 	pub fn print_bytes(&self, bits_per_byte:usize) {
 		for i in 0..(self.stack_len()/bits_per_byte) {
-			let bits = self.slice(i*bits_per_byte, (i+1)*bits_per_byte);
+			let bits = &self.stack[i*bits_per_byte..(i+1)*bits_per_byte];
 			let num = boolvec_to_bignum(bits);
 			print!("{}, ", num);
 		}
 		println!("");
 	}
 
-	pub fn set_bits_boolvec(&mut self, num: &[bool], pos:usize, len:usize) {
+	pub fn set_bits_boolvec(&mut self, num: &[bool], pos:usize, len:usize)
+	-> Result<(), RuntimeErrorType> {
+		self.check_bounds(pos, len)?;
 		for i in 0..len {
 			self.stack[pos + i] = match i < num.len() {
 				true => num[i],
 				false => false,
 			}
 		}
+		Ok(())
 	}
 
-	pub fn set_bits_usize(&mut self, num: usize, pos:usize, len:usize) {
+	pub fn set_bits_usize(&mut self, num: usize, pos:usize, len:usize)
+	-> Result<(), RuntimeErrorType> {
+		self.check_bounds(pos, len)?;
 		for i in 0..len {
 			self.stack[pos + i] = match i < size_of::<usize>()*8 {
 				true => num & (1 << i) != 0,
 				false => false,
 			}
 		}
+		Ok(())
 	}
 
-	pub fn set_bits_bignum(&mut self, num: &gmp::mpz::Mpz, pos:usize, len:usize) {
-		self.set_bits_boolvec(&bignum_to_boolvec(num), pos, len);
+	pub fn set_bits_bignum(&mut self, num: &gmp::mpz::Mpz, pos:usize, len:usize)
+	-> Result<(), RuntimeErrorType> {
+		self.set_bits_boolvec(&bignum_to_boolvec(num), pos, len)
 	}
 
-	pub fn call(&mut self, asm: &Assembler, name: &str) {
+	pub fn call(&mut self, pos: usize) {
 		self.callstack.push(self.instruction);
-		self.instruction = *asm.labels.get(name).expect(
-			format!("No such label of name {}!", name).as_ref()
-		);
+		self.instruction = pos;
+	}
+
+	pub fn ret(&mut self) -> Result<(), RuntimeErrorType> {
+		match self.callstack.pop() {
+			Some(pos) => {
+				self.instruction = pos;
+				Ok(())
+			},
+			None => Err(RuntimeErrorType::EmptyCallStack)
+		}
 	}
 
-	pub fn ret(&mut self) {
-		let pos = self.callstack.pop().expect("Attempt to return on empty call stack!");
+	pub fn goto(&mut self, pos: usize) {
 		self.instruction = pos;
 	}
 
-	pub fn goto(&mut self, asm: &Assembler, name: &str) {
-		self.instruction = *asm.labels.get(name).expect(
-			format!("No such label of name {}!", name).as_ref()
-		);
+	//stops the program, with the lowest 8 bits of 'status' as its exit status
+	pub fn halt(&mut self, status: &[bool]) {
+		let len = status.len().min(8);
+		self.exit_status = Some(boolvec_to_usize(&status[..len]) as i32);
 	}
 }
@@ -1,6 +1,7 @@
 use super::value::Value;
+use super::span::Span;
 use std::fmt;
-use std::process;
+use std::error;
 
 pub enum ArgumentType {
 	Exact(usize),
@@ -16,12 +17,44 @@ pub enum ErrorType {
 	ArgumentError { typename:String, name:String, num:usize, range:ArgumentType },
 	NonExistent { typename: String, value: String },
 	Empty ( String ),
+	FileError { file: String, reason: String },
+	Duplicate { typename: String, value: String, previous: String },
+	Assertion ( String ),
+	UserError ( String ),
+	Warning ( String ),
+	Note ( String ),
 }
 
+pub enum RuntimeErrorType {
+	SizeMismatch { size: usize, expected: usize },
+	OutOfBounds { pos: usize, len: usize, stack: usize },
+	StackUnderflow { bits: usize, stack: usize },
+	EmptyCallStack,
+	UnknownLabel ( String ),
+	UnknownExtCall ( String ),
+	DivisionByZero,
+	NotAPointer ( String ),
+	Io ( String ),
+	InvalidValue ( String ),
+	StepLimit ( usize ),
+}
+
+#[derive(Clone)]
 pub struct Error {
-	text: String,
-	line: usize,
-	file: Option<String>
+	span: Span
+}
+
+pub struct AssembleError {
+	pub kind: Box<ErrorType>,
+	pub span: Span,
+	//the !include lines and macro or loop calls that led to 'span', innermost first
+	pub context: Vec<(&'static str, Span)>
+}
+
+pub struct RuntimeError {
+	pub kind: RuntimeErrorType,
+	pub instruction: usize,
+	pub span: Span
 }
 
 impl ArgumentType {
@@ -57,43 +90,139 @@ impl fmt::Display for ErrorType {
 			ErrorType::NonExistent { ref typename, ref value } =>
 				write!(f, "NameError: no such {} of name '{}'", typename, value),
 			ErrorType::Empty ( ref name ) => write!(f, "{} is empty", name),
-			ErrorType::InvalidPointer ( ref ptr ) => write!(f, "{} is not a valid pointer!", ptr)
+			ErrorType::InvalidPointer ( ref ptr ) => write!(f, "{} is not a valid pointer!", ptr),
+			ErrorType::FileError { ref file, ref reason } =>
+				write!(f, "FileError: could not read file '{}': {}", file, reason),
+			ErrorType::Duplicate { ref typename, ref value, ref previous } =>
+				write!(f, "NameError: {} '{}' was already defined on {}", typename, value, previous),
+			ErrorType::Assertion ( ref message ) => write!(f, "AssertionError: {}", message),
+			ErrorType::UserError ( ref message ) => write!(f, "UserError: {}", message),
+			ErrorType::Warning ( ref message ) => write!(f, "{}", message),
+			ErrorType::Note ( ref message ) => write!(f, "{}", message)
 		}
 	}
 }
 
+impl fmt::Display for RuntimeErrorType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RuntimeErrorType::SizeMismatch { size, expected } =>
+				write!(f, "SizeError: value of {} bit(s) does not fit into {} bit(s)", size, expected),
+			RuntimeErrorType::OutOfBounds { pos, len, stack } =>
+				write!(f, "PointerError: [{}:{}] is outside of the stack of {} bit(s)", pos, len, stack),
+			RuntimeErrorType::StackUnderflow { bits, stack } =>
+				write!(f, "StackError: can not pop {} bit(s) from a stack of {} bit(s)", bits, stack),
+			RuntimeErrorType::EmptyCallStack =>
+				write!(f, "StackError: attempt to return on empty call stack"),
+			RuntimeErrorType::UnknownLabel ( ref name ) =>
+				write!(f, "NameError: no such label of name '{}'", name),
+			RuntimeErrorType::UnknownExtCall ( ref name ) =>
+				write!(f, "NameError: no such external call of name '{}'", name),
+			RuntimeErrorType::DivisionByZero =>
+				write!(f, "MathError: division by zero"),
+			RuntimeErrorType::NotAPointer ( ref val ) =>
+				write!(f, "PointerError: {} is not a pointer", val),
+			RuntimeErrorType::Io ( ref reason ) =>
+				write!(f, "IOError: {}", reason),
+			RuntimeErrorType::InvalidValue ( ref val ) =>
+				write!(f, "InvalidValue: {} is negative or too big", val),
+			RuntimeErrorType::StepLimit ( limit ) =>
+				write!(f, "LimitError: the program did not finish within {} instruction(s)", limit)
+		}
+	}
+}
+
+impl fmt::Display for RuntimeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,
+			"Runtime error at instruction {} on {}, {}.\n>>> {}",
+			self.instruction,
+			self.span.location(),
+			self.kind,
+			self.span.text
+		)
+	}
+}
+
+impl fmt::Debug for RuntimeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Display::fmt(self, f)
+	}
+}
+
+impl error::Error for RuntimeError {
+	fn description(&self) -> &str {
+		"runtime error"
+	}
+}
+
+impl fmt::Display for AssembleError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let severity = match *self.kind {
+			ErrorType::Warning(_) => "Warning",
+			ErrorType::Note(_) => "Note",
+			_ => "Error"
+		};
+		write!(f,
+			"{} on {}, {}.\n>>> {}",
+			severity,
+			self.span.location(),
+			self.kind,
+			self.span.text
+		)?;
+		for &(reason, ref span) in &self.context {
+			write!(f, "\n    {} {}", reason, span.location())?;
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Debug for AssembleError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Display::fmt(self, f)
+	}
+}
+
+impl error::Error for AssembleError {
+	fn description(&self) -> &str {
+		"assembly error"
+	}
+}
+
 #[allow(dead_code)]
 impl Error {
-	pub fn new(text:String, line:usize, filename:Option<String>) -> Error {
+	pub fn new(span: Span) -> Error {
 		Error {
-			text: text,
-			line: line,
-			file: filename
+			span: span
 		}
 	}
 
-	pub fn throw(&self, errortype: ErrorType) -> ! {
-		println!(
-			"Error on line {}{}, {}.\n>>> {}",
-			self.line,
-			match self.file {
-				Some(ref name) => format!(" in file \"{}\"", name),
-				None => "".to_string()
-			},
-			errortype,
-			self.text
-		);
-		process::exit(0)
+	pub fn span(&self) -> &Span {
+		&self.span
+	}
+
+	pub fn location(&self) -> String {
+		self.span.location()
+	}
+
+	pub fn error(&self, errortype: ErrorType) -> AssembleError {
+		AssembleError {
+			kind: Box::new(errortype),
+			span: self.span.clone(),
+			context: Vec::new()
+		}
 	}
 
-	pub fn check_args(&self, typename: &str, name:&str, num:usize, range: ArgumentType) {
+	pub fn check_args(&self, typename: &str, name:&str, num:usize, range: ArgumentType)
+	-> Result<(), AssembleError> {
 		if !range.is_valid(num) {
-			self.throw(ErrorType::ArgumentError{
+			return Err(self.error(ErrorType::ArgumentError{
 				typename: typename.to_string(),
 				name: name.to_string(),
 				num: num,
 				range: range
-			});
+			}));
 		}
+		Ok(())
 	}
 }
@@ -0,0 +1,188 @@
+use super::error::*;
+use super::lexer::*;
+use super::span::Span;
+
+//directives that start and end a block, the lines between them are indented one more level
+const BLOCK_STARTS:&'static [&'static str] = &["if", "ifdef", "ifndef", "macro", "rep", "for"];
+const BLOCK_ENDS:&'static [&'static str] = &["endif", "endmacro", "endrep", "endfor"];
+
+//the name of the directive on a line that starts with '!', e.g. 'define'
+fn directive<'a>(tokens: &[Token], line: &'a str) -> Option<&'a str> {
+	match (tokens.get(0), tokens.get(1)) {
+		(Some(&Token{kind: TokenKind::Bang, ..}), Some(token)) => Some(token.text(line)),
+		_ => None
+	}
+}
+
+fn has_symbol(tokens: &[Token], symbol: char) -> bool {
+	tokens.iter().any(|token| token.kind == TokenKind::Symbol(symbol))
+}
+
+//an instruction with a single space after its name and ', ' between its arguments
+fn format_instruction(tokens: &[Token], line: &str) -> String {
+	let mut ret = tokens[0].text(line).to_string();
+	let args:Vec<&str> = split_arguments(&tokens[1..])
+		.iter()
+		.map(|arg| source_text(arg, line))
+		.collect();
+	if !args.is_empty() {
+		ret.push(' ');
+		ret.push_str(&args.join(", "));
+	}
+	ret
+}
+
+//rewrites source code with consistent indentation and spacing, without changing what it does:
+//labels and directives are indented by how many blocks they are in, and instructions by one more
+//level once the first label has been seen
+pub fn format_source(source: &str, file: Option<String>) -> Result<String, AssembleError> {
+	let mut ret = String::new();
+	let mut depth = 0;
+	let mut in_struct = false;
+	let mut seen_label = false;
+	for (i, line) in source.lines().enumerate() {
+		if i == 0 && is_shebang(line) {
+			ret.push_str(line);
+			ret.push('\n');
+			continue;
+		}
+		let mut tokens = match tokenize(line) {
+			Ok(tokens) => tokens,
+			Err(kind) => {
+				let err = Error::new(Span::from_line(line, 0, line.len(), i + 1, file.clone()));
+				return Err(err.error(kind));
+			}
+		};
+		let comment = match tokens.last() {
+			Some(&Token{kind: TokenKind::Comment(ref text), ..}) => Some(text.trim_end().to_string()),
+			_ => None
+		};
+		strip_comments(&mut tokens);
+
+		let name = directive(&tokens, line);
+		let is_end = match name {
+			Some(name) => BLOCK_ENDS.contains(&name),
+			None => in_struct && has_symbol(&tokens, '}')
+		};
+		let is_middle = name == Some("elif") || name == Some("else");
+		if is_end && depth > 0 {
+			depth -= 1;
+		}
+		let indent = match is_middle && depth > 0 {
+			true => depth - 1,
+			false => depth
+		};
+
+		let code = if tokens.is_empty() {
+			String::new()
+		} else if name.is_some() || in_struct {
+			format!("{}{}", "\t".repeat(indent), source_text(&tokens, line))
+		} else if tokens[0].text(line).starts_with('.') {
+			//a label, which may be followed by an instruction
+			seen_label = true;
+			let label_end = word_end(&tokens, 0);
+			let label = source_text(&tokens[..label_end], line);
+			match label_end < tokens.len() {
+				true => format!("{}{}\t{}", "\t".repeat(indent), label, format_instruction(&tokens[label_end..], line)),
+				false => format!("{}{}", "\t".repeat(indent), label)
+			}
+		} else {
+			let level = indent + match seen_label {
+				true => 1,
+				false => 0
+			};
+			format!("{}{}", "\t".repeat(level), format_instruction(&tokens, line))
+		};
+
+		ret.push_str(&code);
+		if let Some(comment) = comment {
+			match code.len() {
+				0 => ret.push_str(&"\t".repeat(indent)),
+				_ => ret.push(' ')
+			}
+			ret.push(';');
+			ret.push_str(&comment);
+		}
+		ret.push('\n');
+
+		if is_end {
+			in_struct = false;
+		}
+		match name {
+			Some("struct") if !has_symbol(&tokens, '}') => {
+				in_struct = true;
+				depth += 1;
+			},
+			Some(name) if BLOCK_STARTS.contains(&name) => depth += 1,
+			_ => {}
+		}
+	}
+	Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn format(source: &str) -> String {
+		match format_source(source, None) {
+			Ok(formatted) => formatted,
+			Err(e) => panic!("could not format: {}", e)
+		}
+	}
+
+	#[test]
+	fn normalizes_instructions() {
+		assert_eq!(format("mov   [0:8] ,5"), "mov [0:8], 5\n");
+		assert_eq!(format("  ext print,\"a, b\""), "ext print, \"a, b\"\n");
+		assert_eq!(format("ret"), "ret\n");
+	}
+
+	#[test]
+	fn indents_instructions_after_labels() {
+		assert_eq!(
+			format("jmp start\n.start\nmov [0:8],1\n.@loop   add [0:8],[0:8],1\n"),
+			"jmp start\n.start\n\tmov [0:8], 1\n.@loop\tadd [0:8], [0:8], 1\n"
+		);
+	}
+
+	#[test]
+	fn indents_blocks() {
+		assert_eq!(
+			format("!if DEBUG\n!rep 2\n!print \"x\"\n!endrep\n!else\n!print \"y\"\n!endif"),
+			"!if DEBUG\n\t!rep 2\n\t\t!print \"x\"\n\t!endrep\n!else\n\t!print \"y\"\n!endif\n"
+		);
+		assert_eq!(
+			format("!struct Header {\nversion: 4\nlength: 12\n}\n!struct Flags { ack: 1 }"),
+			"!struct Header {\n\tversion: 4\n\tlength: 12\n}\n!struct Flags { ack: 1 }\n"
+		);
+	}
+
+	#[test]
+	fn keeps_comments_and_blank_lines() {
+		assert_eq!(
+			format("mov [0:8],5   ;  five  \n\n;a whole line"),
+			"mov [0:8], 5 ;  five\n\n;a whole line\n"
+		);
+		assert_eq!(format("!macro m\n;inside\n!endmacro"), "!macro m\n\t;inside\n!endmacro\n");
+	}
+
+	#[test]
+	fn keeps_a_shebang_line() {
+		assert_eq!(format("#!/usr/bin/env bit-asm\nhalt"), "#!/usr/bin/env bit-asm\nhalt\n");
+	}
+
+	#[test]
+	fn formatting_twice_changes_nothing() {
+		let once = format("!macro inc v\nadd v,v,1\n!endmacro\n.start\ninc [0:8] ;go\n!ifdef X\n.@a mov [0:8],2\n!endif\n");
+		assert_eq!(format(&once), once);
+	}
+
+	#[test]
+	fn reports_the_line_of_invalid_literals() {
+		match format_source("ret\next print, \"abc", Some("a.asm".to_string())) {
+			Ok(_) => panic!("an unterminated string should not be formatted"),
+			Err(e) => assert_eq!(e.span.line, 2)
+		}
+	}
+}
@@ -1,148 +1,182 @@
 use super::super::value::Value;
 use super::super::environment::Environment;
 use super::super::assembler::Assembler;
-use super::Instruction;
+use super::{Instruction, Label};
 use super::super::error::*;
 
-pub struct Jump { label:String }
-pub struct JumpEqual { label:String, op1:Value, op2:Value }
-pub struct JumpNotEqual { label:String, op1:Value, op2:Value }
-pub struct JumpGreater { label:String, op1:Value, op2:Value }
-pub struct JumpGreaterEqual { label:String, op1:Value, op2:Value }
-pub struct JumpLess { label:String, op1:Value, op2:Value }
-pub struct JumpLessEqual { label:String, op1:Value, op2:Value }
+pub struct Jump { label:Label }
+pub struct JumpEqual { label:Label, op1:Value, op2:Value }
+pub struct JumpNotEqual { label:Label, op1:Value, op2:Value }
+pub struct JumpGreater { label:Label, op1:Value, op2:Value }
+pub struct JumpGreaterEqual { label:Label, op1:Value, op2:Value }
+pub struct JumpLess { label:Label, op1:Value, op2:Value }
+pub struct JumpLessEqual { label:Label, op1:Value, op2:Value }
 
 impl Instruction for Jump {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(1));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(1))?;
 
-		Box::new(
-			Jump { label: args[0].to_string() }
-		)
+		Ok(Box::new(
+			Jump { label: Label::new(args[0]) }
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
-		//Some(self.label.clone())
-		env.goto(asm, self.label.as_ref());
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		env.goto(self.label.get()?);
+		Ok(())
+	}
+
+	fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		self.label.link(resolve)
 	}
 }
 
 impl Instruction for JumpEqual {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3))?;
 
-		Box::new(
+		Ok(Box::new(
 			JumpEqual {
-				op1:   Value::new(args[0], err, false),
-				op2:   Value::new(args[1], err, false),
-				label: args[2].to_string(),
+				op1:   Value::new(args[0], err, false)?,
+				op2:   Value::new(args[1], err, false)?,
+				label: Label::new(args[2]),
 			}
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
-		if self.op1.get_bignum(env) == self.op2.get_bignum(env) {
-			env.goto(asm, self.label.as_ref());
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		if self.op1.get_bignum(env)? == self.op2.get_bignum(env)? {
+			env.goto(self.label.get()?);
 		}
+		Ok(())
+	}
+
+	fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		self.label.link(resolve)
 	}
 }
 
 impl Instruction for JumpNotEqual {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3))?;
 
-		Box::new(
+		Ok(Box::new(
 			JumpNotEqual {
-				op1:   Value::new(args[0], err, false),
-				op2:   Value::new(args[1], err, false),
-				label: args[2].to_string(),
+				op1:   Value::new(args[0], err, false)?,
+				op2:   Value::new(args[1], err, false)?,
+				label: Label::new(args[2]),
 			}
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
-		if self.op1.get_bignum(env) != self.op2.get_bignum(env) {
-			env.goto(asm, self.label.as_ref());
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		if self.op1.get_bignum(env)? != self.op2.get_bignum(env)? {
+			env.goto(self.label.get()?);
 		}
+		Ok(())
+	}
+
+	fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		self.label.link(resolve)
 	}
 }
 
 impl Instruction for JumpGreater {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3))?;
 
-		Box::new(
+		Ok(Box::new(
 			JumpGreater {
-				op1:   Value::new(args[0], err, false),
-				op2:   Value::new(args[1], err, false),
-				label: args[2].to_string(),
+				op1:   Value::new(args[0], err, false)?,
+				op2:   Value::new(args[1], err, false)?,
+				label: Label::new(args[2]),
 			}
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
-		if self.op1.get_bignum(env) > self.op2.get_bignum(env) {
-			env.goto(asm, self.label.as_ref());
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		if self.op1.get_bignum(env)? > self.op2.get_bignum(env)? {
+			env.goto(self.label.get()?);
 		}
+		Ok(())
+	}
+
+	fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		self.label.link(resolve)
 	}
 }
 
 impl Instruction for JumpGreaterEqual {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3))?;
 
-		Box::new(
+		Ok(Box::new(
 			JumpGreaterEqual {
-				op1:   Value::new(args[0], err, false),
-				op2:   Value::new(args[1], err, false),
-				label: args[2].to_string(),
+				op1:   Value::new(args[0], err, false)?,
+				op2:   Value::new(args[1], err, false)?,
+				label: Label::new(args[2]),
 			}
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
-		if self.op1.get_bignum(env) >= self.op2.get_bignum(env) {
-			env.goto(asm, self.label.as_ref());
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		if self.op1.get_bignum(env)? >= self.op2.get_bignum(env)? {
+			env.goto(self.label.get()?);
 		}
+		Ok(())
+	}
+
+	fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		self.label.link(resolve)
 	}
 }
 
 impl Instruction for JumpLess {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3))?;
 
-		Box::new(
+		Ok(Box::new(
 			JumpLess {
-				op1:   Value::new(args[0], err, false),
-				op2:   Value::new(args[1], err, false),
-				label: args[2].to_string(),
+				op1:   Value::new(args[0], err, false)?,
+				op2:   Value::new(args[1], err, false)?,
+				label: Label::new(args[2]),
 			}
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
-		if self.op1.get_bignum(env) < self.op2.get_bignum(env) {
-			env.goto(asm, self.label.as_ref());
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		if self.op1.get_bignum(env)? < self.op2.get_bignum(env)? {
+			env.goto(self.label.get()?);
 		}
+		Ok(())
+	}
+
+	fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		self.label.link(resolve)
 	}
 }
 
 impl Instruction for JumpLessEqual {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(3))?;
 
-		Box::new(
+		Ok(Box::new(
 			JumpLessEqual {
-				op1:   Value::new(args[0], err, false),
-				op2:   Value::new(args[1], err, false),
-				label: args[2].to_string(),
+				op1:   Value::new(args[0], err, false)?,
+				op2:   Value::new(args[1], err, false)?,
+				label: Label::new(args[2]),
 			}
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
-		if self.op1.get_bignum(env) <= self.op2.get_bignum(env) {
-			env.goto(asm, self.label.as_ref());
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		if self.op1.get_bignum(env)? <= self.op2.get_bignum(env)? {
+			env.goto(self.label.get()?);
 		}
+		Ok(())
+	}
+
+	fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		self.label.link(resolve)
 	}
 }
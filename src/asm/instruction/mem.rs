@@ -9,69 +9,67 @@ pub struct Pop( Value);
 pub struct Mov{ to: Value, from: Value }
 
 impl Instruction for Push {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Range(1, 2));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Range(1, 2))?;
 
 		let val = match args.len() >= 2 {
-			true => Some(Value::new(args[1], err, false)),
+			true => Some(Value::new(args[1], err, false)?),
 			false => None
 		};
-		Box::new(
+		Ok(Box::new(
 			Push(
-				Value::new(args[0], err, false),
+				Value::new(args[0], err, false)?,
 				val
 			)
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, _: &Assembler) {
-		let size = self.0.get_usize(env);
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		let size = self.0.get_usize(env)?;
 		let pos = env.stack_len();
 		env.push(size, false);
 		match self.1 {
 			Some(ref val) => {
-				let num = val.get_bignum(env);
-				env.set_bits_bignum(&num, pos, size);
+				let num = val.get_bignum(env)?;
+				env.set_bits_bignum(&num, pos, size)
 			},
-			None => {}
+			None => Ok(())
 		}
 	}
 }
 
 impl Instruction for Pop {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(1));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(1))?;
 
-		Box::new(
-			Pop(Value::new(args[0], err, false))
-		)
+		Ok(Box::new(
+			Pop(Value::new(args[0], err, false)?)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, _: &Assembler) {
-		let size = self.0.get_usize(env);
-		env.pop(size);
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		let size = self.0.get_usize(env)?;
+		env.pop(size)
 	}
 }
 
 impl Instruction for Mov {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(2));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(2))?;
 
-		Box::new(
+		Ok(Box::new(
 			Mov {
-				to: Value::new(args[0], err, true),
-				from: Value::new(args[1], err, false),
+				to: Value::new(args[0], err, true)?,
+				from: Value::new(args[1], err, false)?,
 			}
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, _: &Assembler) {
-		if !self.from.can_coerce(self.to.get_size(env), env) {
-			panic!("Argument is bigger than assignment!");
-		}
-		let pos = self.to.get_ptr_position(&env);
-		let val = self.from.get_bignum(&env);
-		let size = self.to.get_size(env);
-		env.set_bits_bignum(&val, pos, size);
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		let size = self.to.get_size(env)?;
+		self.from.check_coerce(size, env)?;
+		let pos = self.to.get_ptr_position(&env)?;
+		let val = self.from.get_bignum(&env)?;
+		env.set_bits_bignum(&val, pos, size)
 	}
 }
@@ -8,11 +8,52 @@ mod logic;
 mod sys;
 
 pub trait Instruction {
-	fn new(name: &str, arguments: &[&str], err: &Error) -> Box<Instruction> where Self:Sized;
-	fn exec(&self, env: &mut Environment, asm: &Assembler);
+	fn new(name: &str, arguments: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> where Self:Sized;
+	fn exec(&self, env: &mut Environment, asm: &Assembler) -> Result<(), RuntimeErrorType>;
+
+	//called once all lines are parsed, resolves labels into instruction indices
+	fn link(&mut self, _resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		Ok(())
+	}
+}
+
+//A label that is used as an argument, e.g. the target of a jump
+pub struct Label {
+	name: String,
+	target: Option<usize>
+}
+
+impl Label {
+	pub fn new(name: &str) -> Label {
+		Label {
+			name: name.to_string(),
+			target: None
+		}
+	}
+
+	pub fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		match resolve(self.name.as_ref()) {
+			Some(pos) => {
+				self.target = Some(pos);
+				Ok(())
+			},
+			None => Err(ErrorType::NonExistent{
+				typename: "label".to_string(),
+				value: self.name.clone()
+			})
+		}
+	}
+
+	pub fn get(&self) -> Result<usize, RuntimeErrorType> {
+		match self.target {
+			Some(pos) => Ok(pos),
+			None => Err(RuntimeErrorType::UnknownLabel(self.name.clone()))
+		}
+	}
 }
 
-pub fn create_instruction(name: &str, arguments: &[&str], asm: &Assembler, err: &Error) -> Box<Instruction> {
+pub fn create_instruction(name: &str, arguments: &[&str], asm: &Assembler, err: &Error)
+-> Result<Box<Instruction>, AssembleError> {
 	if asm.print_parsed {
 		println!("{}: {}", name, arguments.join(", "));
 	}
@@ -24,6 +65,7 @@ pub fn create_instruction(name: &str, arguments: &[&str], asm: &Assembler, err:
 		"call" => sys::Call::new(name, arguments, err),
 		"ret"  => sys::Ret::new(name, arguments, err),
 		"ext"  => sys::Ext::new(name, arguments, err),
+		"halt" => sys::Halt::new(name, arguments, err),
 
 		"and"  => logic::And::new(name, arguments, err),
 		"or"   => logic::Or::new(name, arguments, err),
@@ -46,9 +88,9 @@ pub fn create_instruction(name: &str, arguments: &[&str], asm: &Assembler, err:
 		"jg"   => jump::JumpGreater::new(name, arguments, err),
 		"jge"  => jump::JumpGreaterEqual::new(name, arguments, err),
 
-		n => err.throw(ErrorType::NonExistent{
+		n => Err(err.error(ErrorType::NonExistent{
 			typename: "instruction".to_string(),
 			value: n.to_string()
-		})
+		}))
 	}
 }
@@ -3,62 +3,88 @@ extern crate gmp;
 use super::super::value::Value;
 use super::super::environment::Environment;
 use super::super::assembler::Assembler;
-use super::Instruction;
+use super::{Instruction, Label};
 use super::super::error::*;
 
 pub struct Ret;
-pub struct Call(String);
+pub struct Call(Label);
 pub struct Ext{name: String, val: Value}
+pub struct Halt(Value);
 
 impl Instruction for Ret {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(0));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(0))?;
 
-		Box::new(
+		Ok(Box::new(
 			Ret
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, _: &Assembler) {
-		env.ret();
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		env.ret()
 	}
 }
 
 impl Instruction for Call {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Exact(1));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Exact(1))?;
 
-		Box::new(
-			Call(args[0].to_string())
-		)
+		Ok(Box::new(
+			Call(Label::new(args[0]))
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
-		env.call(asm, self.0.as_ref());
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		env.call(self.0.get()?);
+		Ok(())
+	}
+
+	fn link(&mut self, resolve: &Fn(&str) -> Option<usize>) -> Result<(), ErrorType> {
+		self.0.link(resolve)
 	}
 }
 
 impl Instruction for Ext {
-	fn new(name: &str, args: &[&str], err: &Error) -> Box<Instruction> {
-		err.check_args("instruction", name, args.len(), ArgumentType::Range(1, 2));
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::Range(1, 2))?;
 
 		let value = match args.len() == 2 {
-			true => Value::new(args[1], err, false),
+			true => Value::new(args[1], err, false)?,
 			false => Value::Bignum(gmp::mpz::Mpz::one())
 		};
-		Box::new(
+		Ok(Box::new(
 			Ext{
 				name: args[0].to_string(),
 				val: value
 			}
-		)
+		))
 	}
 
-	fn exec(&self, env: &mut Environment, asm: &Assembler) {
+	fn exec(&self, env: &mut Environment, asm: &Assembler) -> Result<(), RuntimeErrorType> {
 		let call = asm.ext_calls.get(&self.name);
 		match call {
 			Some(f) => f(&self.val, env, asm),
-			None => panic!("No such external call of name '{}!'", self.name)
+			None => Err(RuntimeErrorType::UnknownExtCall(self.name.clone()))
 		}
 	}
 }
+
+impl Instruction for Halt {
+	fn new(name: &str, args: &[&str], err: &Error) -> Result<Box<Instruction>, AssembleError> {
+		err.check_args("instruction", name, args.len(), ArgumentType::AtMost(1))?;
+
+		let value = match args.len() == 1 {
+			true => Value::new(args[0], err, false)?,
+			false => Value::Bignum(gmp::mpz::Mpz::zero())
+		};
+		Ok(Box::new(
+			Halt(value)
+		))
+	}
+
+	fn exec(&self, env: &mut Environment, _: &Assembler) -> Result<(), RuntimeErrorType> {
+		let status = self.0.get_boolvec(env)?;
+		env.halt(&status);
+		Ok(())
+	}
+}
@@ -0,0 +1,544 @@
+use super::error::ErrorType;
+use std::fmt;
+
+const COMMENT_CHAR:char = ';';
+const ARGUMENT_CHAR:char = ',';
+const STRING_CHAR:char = '"';
+const CHAR_CHAR:char = '\'';
+
+#[derive(Clone, PartialEq)]
+pub enum TokenKind {
+	Ident ( String ),
+	Number ( String ),
+	Str ( Vec<u8> ),
+	Char ( Vec<u8> ),
+	Comment ( String ),
+	LBracket,
+	RBracket,
+	Colon,
+	Comma,
+	Minus,
+	Less,
+	Greater,
+	Bang,
+	Plus,
+	Star,
+	Slash,
+	Percent,
+	Ampersand,
+	Pipe,
+	Caret,
+	ShiftLeft,
+	ShiftRight,
+	Equal,
+	NotEqual,
+	LessEqual,
+	GreaterEqual,
+	LParen,
+	RParen,
+	Symbol ( char ),
+}
+
+#[derive(Clone)]
+pub struct Token {
+	pub kind: TokenKind,
+	pub start: usize,
+	pub end: usize
+}
+
+impl fmt::Display for TokenKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			TokenKind::Ident ( ref name ) => write!(f, "{}", name),
+			TokenKind::Number ( ref num ) => write!(f, "{}", num),
+			TokenKind::Str ( ref s ) => write!(f, "{:?}", String::from_utf8_lossy(s)),
+			TokenKind::Char ( ref s ) => write!(f, "'{}'", String::from_utf8_lossy(s)),
+			TokenKind::Comment ( ref s ) => write!(f, "{}{}", COMMENT_CHAR, s),
+			TokenKind::LBracket => write!(f, "["),
+			TokenKind::RBracket => write!(f, "]"),
+			TokenKind::Colon => write!(f, ":"),
+			TokenKind::Comma => write!(f, "{}", ARGUMENT_CHAR),
+			TokenKind::Minus => write!(f, "-"),
+			TokenKind::Less => write!(f, "<"),
+			TokenKind::Greater => write!(f, ">"),
+			TokenKind::Bang => write!(f, "!"),
+			TokenKind::Plus => write!(f, "+"),
+			TokenKind::Star => write!(f, "*"),
+			TokenKind::Slash => write!(f, "/"),
+			TokenKind::Percent => write!(f, "%"),
+			TokenKind::Ampersand => write!(f, "&"),
+			TokenKind::Pipe => write!(f, "|"),
+			TokenKind::Caret => write!(f, "^"),
+			TokenKind::ShiftLeft => write!(f, "<<"),
+			TokenKind::ShiftRight => write!(f, ">>"),
+			TokenKind::Equal => write!(f, "=="),
+			TokenKind::NotEqual => write!(f, "!="),
+			TokenKind::LessEqual => write!(f, "<="),
+			TokenKind::GreaterEqual => write!(f, ">="),
+			TokenKind::LParen => write!(f, "("),
+			TokenKind::RParen => write!(f, ")"),
+			TokenKind::Symbol ( c ) => write!(f, "{}", c)
+		}
+	}
+}
+
+impl Token {
+	//the part of the source line this token was read from
+	pub fn text<'a>(&self, source: &'a str) -> &'a str {
+		&source[self.start..self.end]
+	}
+
+	pub fn is_ident(&self, name: &str) -> bool {
+		match self.kind {
+			TokenKind::Ident(ref ident) => ident == name,
+			_ => false
+		}
+	}
+}
+
+pub fn is_ident_start(c: char) -> bool {
+	c.is_alphabetic() || c == '_' || c == '.' || c == '@' || c == '$'
+}
+
+pub fn is_ident_char(c: char) -> bool {
+	c.is_alphanumeric() || c == '_' || c == '.' || c == '@' || c == '$'
+}
+
+//a name that is unique to each expansion of a macro, e.g. '%%loop'
+fn is_macro_local(chars: &[(usize, char)], i: usize) -> bool {
+	i + 2 < chars.len() && chars[i].1 == '%' && chars[i + 1].1 == '%' && is_ident_start(chars[i + 2].1)
+}
+
+//reads up to 'max' digits of base 'radix' and returns their value
+fn parse_digits(chars: &[(usize, char)], i: &mut usize, radix: u32, max: usize) -> Option<u32> {
+	let mut ret:u32 = 0;
+	let mut count = 0;
+	while count < max && *i < chars.len() {
+		match chars[*i].1.to_digit(radix) {
+			Some(digit) => ret = ret.saturating_mul(radix).saturating_add(digit),
+			None => break
+		}
+		*i += 1;
+		count += 1;
+	}
+	match count {
+		0 => None,
+		_ => Some(ret)
+	}
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+	let mut buffer = [0; 4];
+	bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+}
+
+//reads the escape sequence after a backslash into 'bytes'
+fn parse_escape(chars: &[(usize, char)], i: &mut usize, bytes: &mut Vec<u8>) -> Result<(), ErrorType> {
+	let escaped = match chars.get(*i) {
+		Some(&(_, c)) => c,
+		None => return Err(ErrorType::Generic("unterminated string literal".to_string()))
+	};
+	*i += 1;
+	match escaped {
+		'\\' => bytes.push(b'\\'),
+		'"'  => bytes.push(b'"'),
+		'\'' => bytes.push(b'\''),
+		'n'  => bytes.push(b'\n'),
+		't'  => bytes.push(b'\t'),
+		'r'  => bytes.push(b'\r'),
+		'x'  => {
+			let start = *i;
+			match parse_digits(chars, i, 16, 2) {
+				Some(byte) if *i - start == 2 => bytes.push(byte as u8),
+				_ => return Err(ErrorType::Generic(
+					"'\\x' must be followed by two hexadecimal digits".to_string()
+				))
+			}
+		},
+		'u'  => {
+			if chars.get(*i).map(|c| c.1) != Some('{') {
+				return Err(ErrorType::Generic("'\\u' must be followed by '{'".to_string()));
+			}
+			*i += 1;
+			let code = parse_digits(chars, i, 16, 6);
+			if chars.get(*i).map(|c| c.1) != Some('}') {
+				return Err(ErrorType::Generic(
+					"'\\u{' must be followed by up to six hexadecimal digits and '}'".to_string()
+				));
+			}
+			*i += 1;
+			match code.and_then(::std::char::from_u32) {
+				Some(c) => push_char(bytes, c),
+				None => return Err(ErrorType::Generic(
+					"'\\u{...}' is not a valid unicode code point".to_string()
+				))
+			}
+		},
+		'0'..='7' => {
+			*i -= 1;
+			match parse_digits(chars, i, 8, 3) {
+				Some(byte) if byte <= 255 => bytes.push(byte as u8),
+				_ => return Err(ErrorType::Generic(
+					"octal escape sequences must be at most '\\377'".to_string()
+				))
+			}
+		},
+		other => return Err(ErrorType::Generic(
+			format!("'\\{}' is not a valid escape sequence", other)
+		))
+	}
+	Ok(())
+}
+
+fn parse_string(chars: &[(usize, char)], i: &mut usize, quote: char) -> Result<Vec<u8>, ErrorType> {
+	let mut ret = Vec::new();
+	//skip opening quote
+	*i += 1;
+	while *i < chars.len() {
+		let c = chars[*i].1;
+		*i += 1;
+		if c == quote {
+			return Ok(ret);
+		}
+		if c == '\\' {
+			parse_escape(chars, i, &mut ret)?;
+		} else {
+			push_char(&mut ret, c);
+		}
+	}
+	Err(ErrorType::Generic("unterminated string literal".to_string()))
+}
+
+//a character literal holds exactly one character or escape sequence, e.g. 'A' or '\n'
+fn parse_char(chars: &[(usize, char)], i: &mut usize) -> Result<Vec<u8>, ErrorType> {
+	let mut ret = Vec::new();
+	//skip opening quote
+	*i += 1;
+	match chars.get(*i).map(|c| c.1) {
+		Some('\\') => {
+			*i += 1;
+			parse_escape(chars, i, &mut ret)?;
+		},
+		Some(c) if c != CHAR_CHAR => {
+			*i += 1;
+			push_char(&mut ret, c);
+		},
+		_ => {}
+	}
+	if ret.is_empty() || chars.get(*i).map(|c| c.1) != Some(CHAR_CHAR) {
+		return Err(ErrorType::Generic(
+			"character literals must contain exactly one character".to_string()
+		));
+	}
+	*i += 1;
+	Ok(ret)
+}
+
+//splits a single line of source code into tokens
+pub fn tokenize(line: &str) -> Result<Vec<Token>, ErrorType> {
+	let chars:Vec<(usize, char)> = line.char_indices().collect();
+	let offset = |i: usize| match i < chars.len() {
+		true => chars[i].0,
+		false => line.len()
+	};
+	let mut tokens:Vec<Token> = Vec::new();
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i].1;
+		let start = i;
+		if c.is_whitespace() {
+			i += 1;
+			continue;
+		}
+		let kind = if c == COMMENT_CHAR {
+			i = chars.len();
+			TokenKind::Comment(line[offset(start) + 1..].to_string())
+
+		} else if c == STRING_CHAR {
+			TokenKind::Str(parse_string(&chars, &mut i, STRING_CHAR)?)
+
+		} else if c == CHAR_CHAR {
+			TokenKind::Char(parse_char(&chars, &mut i)?)
+
+		} else if c.is_digit(10) {
+			//also reads the width and base of literals such as 8'hFF
+			while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_'
+			|| (chars[i].1 == CHAR_CHAR && i + 1 < chars.len() && chars[i + 1].1.is_alphabetic())) {
+				i += 1;
+			}
+			TokenKind::Number(line[offset(start)..offset(i)].to_string())
+
+		} else if is_ident_start(c) || is_macro_local(&chars, i) {
+			if c == '%' {
+				i += 2;
+			}
+			while i < chars.len() && is_ident_char(chars[i].1) {
+				i += 1;
+			}
+			TokenKind::Ident(line[offset(start)..offset(i)].to_string())
+
+		} else if (c == '<' || c == '>') && i + 1 < chars.len() && chars[i + 1].1 == c {
+			i += 2;
+			match c {
+				'<' => TokenKind::ShiftLeft,
+				_ => TokenKind::ShiftRight
+			}
+
+		} else if "=!<>".contains(c) && i + 1 < chars.len() && chars[i + 1].1 == '=' {
+			i += 2;
+			match c {
+				'=' => TokenKind::Equal,
+				'!' => TokenKind::NotEqual,
+				'<' => TokenKind::LessEqual,
+				_ => TokenKind::GreaterEqual
+			}
+
+		} else {
+			i += 1;
+			match c {
+				'[' => TokenKind::LBracket,
+				']' => TokenKind::RBracket,
+				':' => TokenKind::Colon,
+				',' => TokenKind::Comma,
+				'-' => TokenKind::Minus,
+				'<' => TokenKind::Less,
+				'>' => TokenKind::Greater,
+				'!' => TokenKind::Bang,
+				'+' => TokenKind::Plus,
+				'*' => TokenKind::Star,
+				'/' => TokenKind::Slash,
+				'%' => TokenKind::Percent,
+				'&' => TokenKind::Ampersand,
+				'|' => TokenKind::Pipe,
+				'^' => TokenKind::Caret,
+				'(' => TokenKind::LParen,
+				')' => TokenKind::RParen,
+				other => TokenKind::Symbol(other)
+			}
+		};
+		tokens.push(Token {
+			kind: kind,
+			start: offset(start),
+			end: offset(i)
+		});
+	}
+	Ok(tokens)
+}
+
+//a first line such as '#!/usr/bin/env bit-asm', which lets a file be run as a script
+pub fn is_shebang(line: &str) -> bool {
+	line.starts_with("#!")
+}
+
+//splits a line on every '\n' that is outside of a literal or comment, so several statements
+//can be written on one line, e.g. with --text
+pub fn split_statements(line: &str) -> Vec<&str> {
+	let tokens = match tokenize(line) {
+		Ok(tokens) => tokens,
+		Err(_) => return vec![line]
+	};
+	let mut ret = Vec::new();
+	let mut start = 0;
+	for token in &tokens {
+		if token.kind == TokenKind::Symbol('\\') && line[token.end..].starts_with('n') {
+			ret.push(&line[start..token.start]);
+			start = token.end + 1;
+		}
+	}
+	ret.push(&line[start..]);
+	ret
+}
+
+//removes the comment at the end of a list of tokens, if there is one
+pub fn strip_comments(tokens: &mut Vec<Token>) {
+	tokens.retain(|token| match token.kind {
+		TokenKind::Comment(_) => false,
+		_ => true
+	});
+}
+
+//splits a list of tokens on every comma that is not inside of brackets or parentheses
+pub fn split_arguments(tokens: &[Token]) -> Vec<&[Token]> {
+	let mut ret = Vec::new();
+	let mut depth = 0;
+	let mut start = 0;
+	for (i, token) in tokens.iter().enumerate() {
+		match token.kind {
+			TokenKind::LBracket | TokenKind::LParen => depth += 1,
+			TokenKind::RBracket | TokenKind::RParen => depth -= 1,
+			TokenKind::Comma if depth <= 0 => {
+				ret.push(&tokens[start..i]);
+				start = i + 1;
+			},
+			_ => {}
+		}
+	}
+	ret.push(&tokens[start..]);
+	ret.into_iter().filter(|arg| !arg.is_empty()).collect()
+}
+
+//the index after a group of tokens that are not separated by whitespace, e.g. 'first-start'
+pub fn word_end(tokens: &[Token], start: usize) -> usize {
+	let mut i = start + 1;
+	while i < tokens.len() && tokens[i].start == tokens[i - 1].end {
+		i += 1;
+	}
+	i
+}
+
+//rebuilds a line with every name that 'replace' returns a value for substituted
+pub fn substitute<F>(source: &str, tokens: &[Token], replace: F) -> String
+	where F: Fn(&str) -> Option<String> {
+
+	let mut ret = String::new();
+	let mut last = match tokens.first() {
+		Some(token) => token.start,
+		None => return ret
+	};
+	for token in tokens {
+		ret.push_str(&source[last..token.start]);
+		let replaced = match token.kind {
+			TokenKind::Ident(ref name) => replace(name),
+			_ => None
+		};
+		match replaced {
+			Some(text) => ret.push_str(&text),
+			None => ret.push_str(token.text(source))
+		}
+		last = token.end;
+	}
+	ret
+}
+
+//the source text from the start of the first token to the end of the last token
+pub fn source_text<'a>(tokens: &[Token], source: &'a str) -> &'a str {
+	match (tokens.first(), tokens.last()) {
+		(Some(first), Some(last)) => &source[first.start..last.end],
+		_ => ""
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn tokens(line: &str) -> Vec<Token> {
+		match tokenize(line) {
+			Ok(tokens) => tokens,
+			Err(kind) => panic!("'{}' could not be tokenized: {}", line, kind)
+		}
+	}
+
+	fn kinds(line: &str) -> Vec<String> {
+		tokens(line).iter().map(|token| format!("{}", token.kind)).collect()
+	}
+
+	fn texts(line: &str, args: Vec<&[Token]>) -> Vec<String> {
+		args.iter().map(|arg| source_text(arg, line).to_string()).collect()
+	}
+
+	#[test]
+	fn tokenizes_instructions() {
+		assert_eq!(kinds("mov [0:8], 5"), vec!["mov", "[", "0", ":", "8", "]", ",", "5"]);
+		assert_eq!(kinds("jne [-0:8], 0, @loop"), vec!["jne", "[", "-", "0", ":", "8", "]", ",", "0", ",", "@loop"]);
+		assert_eq!(kinds("!define top <[-0]"), vec!["!", "define", "top", "<", "[", "-", "0", "]"]);
+	}
+
+	#[test]
+	fn tokenizes_operators() {
+		assert_eq!(kinds("a<<b>>c"), vec!["a", "<<", "b", ">>", "c"]);
+		assert_eq!(kinds("a<=b>=c==d!=e"), vec!["a", "<=", "b", ">=", "c", "==", "d", "!=", "e"]);
+		assert_eq!(kinds("(1+2)*3%4/5&6|7^8"), vec!["(", "1", "+", "2", ")", "*", "3", "%", "4", "/", "5", "&", "6", "|", "7", "^", "8"]);
+	}
+
+	#[test]
+	fn keeps_comment_characters_in_strings() {
+		assert_eq!(kinds("ext print, \"a;b\" ;prints 'a;b'"), vec!["ext", "print", ",", "\"a;b\"", ";prints 'a;b'"]);
+		let mut tokens = tokens("ext print, \"a;b\" ;comment");
+		strip_comments(&mut tokens);
+		assert_eq!(tokens.len(), 4);
+	}
+
+	#[test]
+	fn reads_macro_local_names() {
+		assert_eq!(kinds("jmp %%loop"), vec!["jmp", "%%loop"]);
+		assert_eq!(kinds("5 %% 3"), vec!["5", "%", "%", "3"]);
+	}
+
+	#[test]
+	fn splits_arguments_outside_of_brackets() {
+		let line = "mov [0:8], [(1 + 2):8], \"a, b\"";
+		let tokens = tokens(line);
+		assert_eq!(texts(line, split_arguments(&tokens[1..])), vec!["[0:8]", "[(1 + 2):8]", "\"a, b\""]);
+		assert_eq!(split_arguments(&[]).len(), 0);
+	}
+
+	#[test]
+	fn finds_the_end_of_words() {
+		let line = ".first-start mov";
+		let tokens = tokens(line);
+		assert_eq!(word_end(&tokens, 0), 3);
+		assert_eq!(source_text(&tokens[..3], line), ".first-start");
+	}
+
+	#[test]
+	fn substitutes_names() {
+		let line = "mov rax, rax + rbx";
+		let tokens = tokens(line);
+		let replaced = substitute(line, &tokens, |name| match name {
+			"rax" => Some("[0:64]".to_string()),
+			_ => None
+		});
+		assert_eq!(replaced, "mov [0:64], [0:64] + rbx");
+	}
+
+	fn string(literal: &str) -> Vec<u8> {
+		match tokens(literal)[0].kind {
+			TokenKind::Str(ref bytes) | TokenKind::Char(ref bytes) => bytes.clone(),
+			ref other => panic!("'{}' is not a string literal but {}", literal, other)
+		}
+	}
+
+	#[test]
+	fn reads_escape_sequences() {
+		assert_eq!(string(r#""\\ \" \'""#), b"\\ \" '".to_vec());
+		assert_eq!(string(r#""\n\t\r\0""#), b"\n\t\r\0".to_vec());
+		assert_eq!(string(r#""\x7F\x41""#), vec![0x7F, 0x41]);
+		assert_eq!(string(r#""\101\377\0""#), vec![0o101, 0o377, 0]);
+		assert_eq!(string(r#""\u{41}\u{1F600}""#), "A\u{1F600}".as_bytes().to_vec());
+		assert_eq!(string(r#"'\n'"#), b"\n".to_vec());
+		assert_eq!(string("'é'"), "é".as_bytes().to_vec());
+	}
+
+	#[test]
+	fn rejects_invalid_escape_sequences() {
+		for literal in &[r#""\q""#, r#""\x4""#, r#""\xZZ""#, r#""\400""#, r#""\u41""#,
+			r#""\u{1234567}""#, r#""\u{D800}""#, r#""abc\"#] {
+			assert!(tokenize(literal).is_err(), "'{}' should not be valid", literal);
+		}
+	}
+
+	#[test]
+	fn splits_statements_outside_of_literals() {
+		assert_eq!(split_statements(r#"push 8\nmov [0:8], 5"#), vec!["push 8", "mov [0:8], 5"]);
+		assert_eq!(split_statements(r#"ext print, "a\nb"\nret"#), vec![r#"ext print, "a\nb""#, "ret"]);
+		assert_eq!(split_statements(r#"ext print, '\n'"#), vec![r#"ext print, '\n'"#]);
+		assert_eq!(split_statements(r#"ret ;a\nb"#), vec![r#"ret ;a\nb"#]);
+		assert_eq!(split_statements("ret"), vec!["ret"]);
+		assert_eq!(split_statements(r#"ext print, "abc\n"#), vec![r#"ext print, "abc\n"#]);
+	}
+
+	#[test]
+	fn recognizes_shebang_lines() {
+		assert!(is_shebang("#!/usr/bin/env bit-asm"));
+		assert!(!is_shebang(" #!/usr/bin/env bit-asm"));
+		assert!(!is_shebang("!define a 1"));
+	}
+
+	#[test]
+	fn rejects_unterminated_literals() {
+		assert!(tokenize("ext print, \"abc").is_err());
+		assert!(tokenize("mov [0:8], 'ab'").is_err());
+		assert!(tokenize("mov [0:8], ''").is_err());
+	}
+}
@@ -4,3 +4,6 @@ pub mod environment;
 pub mod util;
 pub mod value;
 pub mod error;
+pub mod span;
+pub mod lexer;
+pub mod format;
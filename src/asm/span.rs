@@ -0,0 +1,53 @@
+use std::fmt;
+
+//The location of a piece of source code, kept for every compiled instruction
+#[derive(Clone)]
+pub struct Span {
+	pub file: Option<String>,
+	pub line: usize,
+	pub columns: (usize, usize),
+	pub text: String
+}
+
+impl Span {
+	pub fn new(text: String, line: usize, columns: (usize, usize), file: Option<String>) -> Span {
+		Span {
+			file: file,
+			line: line,
+			columns: columns,
+			text: text
+		}
+	}
+
+	//creates a span for the bytes from 'start' to 'end' of the source line 'source'
+	pub fn from_line(source: &str, start: usize, end: usize, line: usize, file: Option<String>) -> Span {
+		let text = &source[start..end];
+		let start = source[..start].chars().count() + 1;
+		let end = start + text.chars().count();
+		Span::new(text.to_string(), line, (start, end), file)
+	}
+
+	pub fn location(&self) -> String {
+		format!("line {}{}",
+			self.line,
+			match self.file {
+				Some(ref name) => format!(" in file \"{}\"", name),
+				None => "".to_string()
+			}
+		)
+	}
+}
+
+impl fmt::Display for Span {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}:{}-{}",
+			match self.file {
+				Some(ref name) => name.as_ref(),
+				None => "<text>"
+			},
+			self.line,
+			self.columns.0,
+			self.columns.1
+		)
+	}
+}
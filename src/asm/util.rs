@@ -1,14 +1,6 @@
 #![allow(dead_code)]
 extern crate gmp;
-
-pub fn remove_comments(value: &mut String, comment: char) {
-	match value.find(comment) {
-		Some(pos) => {
-			value.truncate(pos);
-		},
-		_ => {}
-	}
-}
+use std::mem::size_of;
 
 pub fn boolvec_to_bignum(vec:&[bool]) -> gmp::mpz::Mpz {
 	let mut ret = gmp::mpz::Mpz::zero();
@@ -49,13 +41,30 @@ pub fn boolvec_to_u8(vec:&[bool]) -> u8 {
 	return ret;
 }
 
+//the utf-8 text in 'vec', up to the first null character
+pub fn boolvec_to_string(vec:&[bool]) -> String {
+	let mut chars:Vec<u8> = vec![];
+	for i in 0..(vec.len()/8) {
+		let c = boolvec_to_u8(&vec[i*8..(i+1)*8]);
+		if c == 0 {
+			break;
+		}
+		chars.push(c);
+	}
+	String::from_utf8_lossy(chars.as_slice()).to_string()
+}
+
 pub fn char_to_boolvec(c:char) -> Vec<bool> {
 	let mut temp_string:String = String::new();
 	temp_string.push(c);
 	return str_to_boolvec(temp_string.as_ref());
 }
 
-pub fn bignum_to_usize(num: &gmp::mpz::Mpz) -> usize {
+//None if 'num' is negative or too big for a usize
+pub fn bignum_to_usize(num: &gmp::mpz::Mpz) -> Option<usize> {
+	if *num < gmp::mpz::Mpz::zero() || num.bit_length() > size_of::<usize>()*8 {
+		return None;
+	}
 	let mut ret:usize = 0;
 
 	for i in 0..num.bit_length() {
@@ -64,7 +73,7 @@ pub fn bignum_to_usize(num: &gmp::mpz::Mpz) -> usize {
 		}
 	}
 
-	return ret;
+	return Some(ret);
 }
 
 pub fn bignum_to_boolvec(num: &gmp::mpz::Mpz) -> Vec<bool> {
@@ -77,9 +86,13 @@ pub fn bignum_to_boolvec(num: &gmp::mpz::Mpz) -> Vec<bool> {
 }
 
 pub fn str_to_boolvec(s:&str) -> Vec<bool> {
+	bytes_to_boolvec(s.as_bytes())
+}
+
+pub fn bytes_to_boolvec(bytes:&[u8]) -> Vec<bool> {
 	let mut ret:Vec<bool> = vec![];
 
-	for b in s.as_bytes() {
+	for b in bytes {
 		for i in 0..8 {
 			ret.push((*b as u32) & (128 >> i) != 0);
 		}
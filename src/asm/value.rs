@@ -1,17 +1,128 @@
-extern crate regex;
 extern crate gmp;
 use std::fmt;
-use self::regex::Regex;
 use std::str::FromStr;
 use super::environment::Environment;
+use super::lexer::*;
 use super::util::*;
 use super::error::*;
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operator {
+	Add,
+	Sub,
+	Mul,
+	Div,
+	Mod,
+	ShiftLeft,
+	ShiftRight,
+	And,
+	Or,
+	Xor,
+	Equal,
+	NotEqual,
+	Less,
+	LessEqual,
+	Greater,
+	GreaterEqual
+}
+
 pub enum Value {
 	Boolvec (Vec<bool>),
 	Bignum (gmp::mpz::Mpz),
 	Pointer {pos:Box<Value>, len:Box<Value>, rev:bool},
-	Position (Box<Value>, bool)
+	Position (Box<Value>, bool),
+	Expression {op:Operator, lhs:Box<Value>, rhs:Box<Value>}
+}
+
+//the largest number of bits a value can be shifted left by, so a typo can't use up all memory
+const MAX_SHIFT:usize = 1 << 24;
+
+//the number of bits to shift by, shifting right by more bits than 'lhs' has always gives the same result
+fn shift_amount(lhs: &gmp::mpz::Mpz, rhs: &gmp::mpz::Mpz, left: bool) -> Result<usize, RuntimeErrorType> {
+	let limit = match left {
+		true => MAX_SHIFT,
+		false => lhs.bit_length() + 1
+	};
+	match (bignum_to_usize(rhs), *rhs < gmp::mpz::Mpz::zero()) {
+		(Some(amount), _) if amount <= limit => Ok(amount),
+		(_, false) if !left => Ok(limit),
+		_ => Err(RuntimeErrorType::InvalidValue(format!("shift of {}", rhs)))
+	}
+}
+
+impl Operator {
+	//binary operators from lowest to highest precedence
+	fn levels() -> Vec<Vec<(TokenKind, Operator)>> {
+		vec![
+			vec![(TokenKind::Pipe, Operator::Or)],
+			vec![(TokenKind::Caret, Operator::Xor)],
+			vec![(TokenKind::Ampersand, Operator::And)],
+			vec![(TokenKind::Equal, Operator::Equal), (TokenKind::NotEqual, Operator::NotEqual)],
+			vec![(TokenKind::Less, Operator::Less), (TokenKind::LessEqual, Operator::LessEqual),
+				(TokenKind::Greater, Operator::Greater), (TokenKind::GreaterEqual, Operator::GreaterEqual)],
+			vec![(TokenKind::ShiftLeft, Operator::ShiftLeft), (TokenKind::ShiftRight, Operator::ShiftRight)],
+			vec![(TokenKind::Plus, Operator::Add), (TokenKind::Minus, Operator::Sub)],
+			vec![(TokenKind::Star, Operator::Mul), (TokenKind::Slash, Operator::Div), (TokenKind::Percent, Operator::Mod)],
+		]
+	}
+
+	pub fn apply(&self, lhs: gmp::mpz::Mpz, rhs: gmp::mpz::Mpz) -> Result<gmp::mpz::Mpz, RuntimeErrorType> {
+		if (*self == Operator::Div || *self == Operator::Mod) && rhs == gmp::mpz::Mpz::zero() {
+			return Err(RuntimeErrorType::DivisionByZero);
+		}
+		//comparisons result in 1 if they hold and 0 otherwise
+		let compare = |holds: bool| match holds {
+			true => gmp::mpz::Mpz::one(),
+			false => gmp::mpz::Mpz::zero()
+		};
+		Ok(match *self {
+			Operator::Add => lhs + rhs,
+			Operator::Sub => lhs - rhs,
+			Operator::Mul => lhs * rhs,
+			Operator::Div => lhs / rhs,
+			Operator::Mod => lhs % rhs,
+			Operator::ShiftLeft => {
+				let amount = shift_amount(&lhs, &rhs, true)?;
+				lhs << amount
+			},
+			Operator::ShiftRight => {
+				let amount = shift_amount(&lhs, &rhs, false)?;
+				lhs >> amount
+			},
+			Operator::And => lhs & rhs,
+			Operator::Or => lhs | rhs,
+			Operator::Xor => lhs ^ rhs,
+			Operator::Equal => compare(lhs == rhs),
+			Operator::NotEqual => compare(lhs != rhs),
+			Operator::Less => compare(lhs < rhs),
+			Operator::LessEqual => compare(lhs <= rhs),
+			Operator::Greater => compare(lhs > rhs),
+			Operator::GreaterEqual => compare(lhs >= rhs)
+		})
+	}
+}
+
+impl fmt::Display for Operator {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", match *self {
+			Operator::Add => "+",
+			Operator::Sub => "-",
+			Operator::Mul => "*",
+			Operator::Div => "/",
+			Operator::Mod => "%",
+			Operator::ShiftLeft => "<<",
+			Operator::ShiftRight => ">>",
+			Operator::And => "&",
+			Operator::Or => "|",
+			Operator::Xor => "^",
+			Operator::Equal => "==",
+			Operator::NotEqual => "!=",
+			Operator::Less => "<",
+			Operator::LessEqual => "<=",
+			Operator::Greater => ">",
+			Operator::GreaterEqual => ">="
+		})
+	}
 }
 
 impl fmt::Display for Value {
@@ -28,7 +139,8 @@ impl fmt::Display for Value {
 					true => '>',
 					false => '<'
 				}, format!("{}", ptr))
-			}
+			},
+			Value::Expression{ref op, ref lhs, ref rhs} => write!(f, "({} {} {})", lhs, op, rhs)
 		}
 	}
 }
@@ -42,194 +154,471 @@ impl Value {
 		}
 	}
 
-	pub fn get_ptr_position(&self, env: &Environment) -> usize {
+	//the value of this if it can be known without running the program
+	pub fn constant(&self) -> Option<gmp::mpz::Mpz> {
 		match *self {
-			Value::Pointer{ref pos, ref rev, ..} => match *rev{
-				true => env.stack_len() - pos.get_usize(env),
-				false => pos.get_usize(env)
+			Value::Bignum(ref num) => Some(num.clone()),
+			Value::Boolvec(ref vec) => Some(boolvec_to_bignum(vec)),
+			Value::Pointer{..} => None,
+			Value::Position(ref ptr, rev) => match **ptr {
+				Value::Pointer{ref pos, ref len, rev: false} => {
+					match (pos.constant(), len.constant(), rev) {
+						(Some(pos), Some(len), true) => Some(pos + len),
+						(Some(pos), _, false) => Some(pos),
+						_ => None
+					}
+				},
+				_ => None
 			},
-			_ => panic!("This value, {}, is not a pointer!", self)
+			Value::Expression{ref op, ref lhs, ref rhs} => {
+				match (lhs.constant(), rhs.constant()) {
+					(Some(lhs), Some(rhs)) => op.apply(lhs, rhs).ok(),
+					_ => None
+				}
+			}
 		}
 	}
 
-	pub fn get_usize(&self, env:&Environment) -> usize {
-		bignum_to_usize(&self.get_bignum(env))
+	pub fn get_ptr_position(&self, env: &Environment) -> Result<usize, RuntimeErrorType> {
+		match *self {
+			Value::Pointer{ref pos, ref rev, ..} => {
+				let pos = pos.get_usize(env)?;
+				match *rev {
+					true => match env.stack_len() >= pos {
+						true => Ok(env.stack_len() - pos),
+						false => Err(RuntimeErrorType::OutOfBounds{
+							pos: pos,
+							len: self.get_ptr_size(env)?,
+							stack: env.stack_len()
+						})
+					},
+					false => Ok(pos)
+				}
+			},
+			_ => Err(RuntimeErrorType::NotAPointer(format!("{}", self)))
+		}
 	}
 
-	pub fn get_bignum(&self, env: &Environment) -> gmp::mpz::Mpz {
-		match *self {
+	pub fn get_usize(&self, env:&Environment) -> Result<usize, RuntimeErrorType> {
+		let num = self.get_bignum(env)?;
+		match bignum_to_usize(&num) {
+			Some(num) => Ok(num),
+			None => Err(RuntimeErrorType::InvalidValue(format!("{}", num)))
+		}
+	}
+
+	//position of the pointer a Position value refers to
+	fn get_position_value(ptr: &Value, rev: bool, env: &Environment) -> Result<usize, RuntimeErrorType> {
+		let mut pos = ptr.get_ptr_position(env)?;
+		if rev {
+			pos += ptr.get_ptr_size(env)?;
+		}
+		Ok(pos)
+	}
+
+	pub fn get_bignum(&self, env: &Environment) -> Result<gmp::mpz::Mpz, RuntimeErrorType> {
+		Ok(match *self {
 			Value::Bignum(ref num) => num.clone(),
 			Value::Boolvec(ref vec) => boolvec_to_bignum(vec),
 			Value::Pointer{..} => {
-				let size = self.get_ptr_size(env);
-				let pos = self.get_ptr_position(env);
-				let bits = env.slice(pos, pos + size);
+				let size = self.get_ptr_size(env)?;
+				let pos = self.get_ptr_position(env)?;
+				let bits = env.slice(pos, pos + size)?;
 				boolvec_to_bignum(bits)
 			},
 			Value::Position(ref ptr, rev) => {
-				let mut pos = ptr.get_ptr_position(env);
-				if rev {
-					pos += ptr.get_ptr_size(env);
-				}
-				usize_to_bignum(pos)
+				usize_to_bignum(Value::get_position_value(ptr, rev, env)?)
+			},
+			Value::Expression{ref op, ref lhs, ref rhs} => {
+				op.apply(lhs.get_bignum(env)?, rhs.get_bignum(env)?)?
 			}
-		}
+		})
 	}
 
-	pub fn get_boolvec(&self, env: &Environment) -> Vec<bool> {
-		match *self {
+	pub fn get_boolvec(&self, env: &Environment) -> Result<Vec<bool>, RuntimeErrorType> {
+		Ok(match *self {
 			Value::Bignum(ref num) => bignum_to_boolvec(num),
 			Value::Boolvec(ref vec) => vec.clone(),
 			Value::Pointer{..} => {
-				let size = self.get_ptr_size(env);
-				let pos = self.get_ptr_position(env);
-				let bits = env.slice(pos, pos + size);
+				let size = self.get_ptr_size(env)?;
+				let pos = self.get_ptr_position(env)?;
+				let bits = env.slice(pos, pos + size)?;
 				bits.to_vec()
 			},
 			Value::Position(ref ptr, rev) => {
-				let mut pos = ptr.get_ptr_position(env);
-				if rev {
-					pos += ptr.get_ptr_size(env);
-				}
-				usize_to_boolvec(pos)
-			}
-		}
+				usize_to_boolvec(Value::get_position_value(ptr, rev, env)?)
+			},
+			Value::Expression{..} => bignum_to_boolvec(&self.get_bignum(env)?)
+		})
 	}
 
-	pub fn get_ptr_size(&self, env: &Environment) -> usize {
+	pub fn get_ptr_size(&self, env: &Environment) -> Result<usize, RuntimeErrorType> {
 		match *self {
 			Value::Pointer{ref len, ..} => len.get_usize(env),
-			_ => panic!("This value, {}, is not a pointer!", self)
+			_ => Err(RuntimeErrorType::NotAPointer(format!("{}", self)))
 		}
 	}
 
-	pub fn can_coerce(&self, new_size:usize, env: &Environment) -> bool {
-		match *self {
-			Value::Pointer{ref len, ..} => len.get_usize(env) <= new_size,
-			Value::Boolvec(ref vec) => vec.len() <= new_size,
-			Value::Bignum(ref num) => num.bit_length() <= new_size,
-			Value::Position(ref ptr, rev) => {
-				let mut pos = ptr.get_ptr_position(env);
-				if rev {
-					pos += ptr.get_ptr_size(env);
-				}
-				usize_len(pos) <= new_size
-			}
+	pub fn can_coerce(&self, new_size:usize, env: &Environment) -> Result<bool, RuntimeErrorType> {
+		Ok(self.get_size(env)? <= new_size)
+	}
+
+	//returns an error if this value can not fit into 'new_size' bits
+	pub fn check_coerce(&self, new_size:usize, env: &Environment) -> Result<(), RuntimeErrorType> {
+		let size = self.get_size(env)?;
+		match size <= new_size {
+			true => Ok(()),
+			false => Err(RuntimeErrorType::SizeMismatch{
+				size: size,
+				expected: new_size
+			})
 		}
 	}
 
-	pub fn get_size(&self, env: &Environment) -> usize {
-		match *self {
-			Value::Pointer{ref len, ..} => len.get_usize(env),
+	pub fn get_size(&self, env: &Environment) -> Result<usize, RuntimeErrorType> {
+		Ok(match *self {
+			Value::Pointer{ref len, ..} => len.get_usize(env)?,
 			Value::Boolvec(ref vec) => vec.len(),
 			Value::Bignum(ref num) => num.bit_length(),
 			Value::Position(ref ptr, rev) => {
-				let mut pos = ptr.get_ptr_position(env);
-				if rev {
-					pos += ptr.get_ptr_size(env);
-				}
-				usize_len(pos)
-			}
-		}
+				usize_len(Value::get_position_value(ptr, rev, env)?)
+			},
+			Value::Expression{..} => self.get_bignum(env)?.bit_length()
+		})
 	}
 
-	pub fn new(value:&str, err: &Error, require_pointer: bool) -> Value {
-		let ret = match Value::create(value, err) {
-			Some(ret) => ret,
-			None => err.throw(ErrorType::InvalidValue(value.to_string()))
+	pub fn new(value:&str, err: &Error, require_pointer: bool) -> Result<Value, AssembleError> {
+		let tokens = match tokenize(value) {
+			Ok(tokens) => tokens,
+			Err(kind) => return Err(err.error(kind))
 		};
+		let mut pos = 0;
+		let ret = Value::parse_expression(&tokens, &mut pos, 0, value, err)?;
+		if pos < tokens.len() {
+			return Err(err.error(ErrorType::InvalidValue(value.to_string())));
+		}
 		if require_pointer && !ret.is_ptr() {
-			err.throw(ErrorType::InvalidPointer(ret));
+			return Err(err.error(ErrorType::InvalidPointer(ret)));
 		};
-		ret
-	}
-
-	fn create(value:&str, err: &Error) -> Option<Value> {
-		let value = value.trim();
-		//println!("Parsing: '{}'", value);
-		let re_ptr = Regex::new(r"^\[(.*?)\]$").unwrap();
-
-		if re_ptr.is_match(value) {
-			//Is a pointer
-			let mut args = ("".to_string(), "".to_string());
-			let mut bracket_n = 0;
-			let mut i = 0;
-			let mut arg_i = 0;
-			let mut from_back = false;
-			for c in value.chars() {
-				let skip = i < 1 || i >= value.len() - 1 || c.is_whitespace();
-				i += 1;
-				if skip {continue};
-
-				if c == '[' {
-					bracket_n += 1;
-				} else if c == ']' {
-					bracket_n -= 1;
-				}
-				if c == ':' && bracket_n == 0 {
-					arg_i = 1;
-				} else if c == '-' && args.0.len() == 0 && arg_i == 0 {
-					from_back = true;
-				} else  {
-					if      arg_i == 0 {args.0.push(c);}
-					else if arg_i == 1 {args.1.push(c);}
-				}
-			}
-			if args.1 == "" {args.1 = "1".to_string();}
-			let position = Value::create(&args.0, err);
-			let length = Value::create(&args.1, err);
-			return match (position, length) {
-				(Some(pos_val), Some(len_val)) => {
-					Some(Value::Pointer {
-						pos: Box::new(pos_val),
-						len: Box::new(len_val),
-						rev: from_back
-					})
-				},
-				_ => None
-			}
-		} else if value.chars().next() == Some('b') {
-			let mut boolvec:Vec<bool> = vec![];
-			boolvec.reserve(value.len() - 1);
-			for c in value[1..].chars() {
-				boolvec.push(match c{
-					'0' => false,
-					'1' => true,
-					other => err.throw(ErrorType::Generic(
-						format!("'{}' is not a valid character in a boolvec!", other)
-					))
-				});
+		Ok(ret)
+	}
+
+	fn parse_digits(digits: &str, radix: u8, text: &str) -> Result<gmp::mpz::Mpz, ErrorType> {
+		let digits:String = digits.chars().filter(|c| *c != '_').collect();
+		let is_valid = !digits.is_empty() && !digits.starts_with('+') && !digits.starts_with('-');
+		match is_valid {
+			true => match gmp::mpz::Mpz::from_str_radix(&digits, radix) {
+				Ok(val) => Ok(val),
+				Err(_) => Err(ErrorType::InvalidValue(text.to_string()))
+			},
+			false => Err(ErrorType::InvalidValue(text.to_string()))
+		}
+	}
+
+	//parses a numeric literal such as 1234, 0xFF, 0o17, 0b1100, 1_000 or 8'hFF
+	fn parse_number(text: &str) -> Result<Value, ErrorType> {
+		//width-annotated literal, always exactly 'width' bits long
+		if let Some(pos) = text.find('\'') {
+			let width = match usize::from_str(&text[..pos].replace("_", "")) {
+				Ok(width) => width,
+				Err(_) => return Err(ErrorType::InvalidValue(text.to_string()))
+			};
+			let radix = match text[pos + 1..].chars().next() {
+				Some('h') | Some('x') => 16,
+				Some('d') => 10,
+				Some('o') => 8,
+				Some('b') => 2,
+				_ => return Err(ErrorType::InvalidValue(text.to_string()))
+			};
+			let num = Value::parse_digits(&text[pos + 2..], radix, text)?;
+			if num.bit_length() > width {
+				return Err(ErrorType::Generic(
+					format!("'{}' does not fit into {} bit(s)", text, width)
+				));
 			}
-			return Some(Value::Boolvec(boolvec));
+			let mut boolvec = bignum_to_boolvec(&num);
+			boolvec.resize(width, false);
+			return Ok(Value::Boolvec(boolvec));
+		}
+		let (digits, radix) = match text.get(..2) {
+			Some("0x") | Some("0X") => (&text[2..], 16),
+			Some("0o") | Some("0O") => (&text[2..], 8),
+			Some("0b") | Some("0B") => (&text[2..], 2),
+			_ => (text, 10)
+		};
+		Ok(Value::Bignum(Value::parse_digits(digits, radix, text)?))
+	}
 
-		} else if value.chars().next() == Some('<') {
-			return match Value::create(&value[1..], err) {
-				Some (val) => {
-					if !val.is_ptr() {
-						err.throw(ErrorType::InvalidPointer(val));
-					}
-					Some(Value::Position(Box::new(val), false))
-				}
+	//combines two values, folding them into a single number if both are constant
+	fn fold(op: Operator, lhs: Value, rhs: Value, err: &Error) -> Result<Value, AssembleError> {
+		if let (Some(a), Some(b)) = (lhs.constant(), rhs.constant()) {
+			return match op.apply(a, b) {
+				Ok(num) => Ok(Value::Bignum(num)),
+				Err(kind) => Err(err.error(ErrorType::Generic(
+					format!("{} in constant expression", kind)
+				)))
+			};
+		}
+		Ok(Value::Expression {
+			op: op,
+			lhs: Box::new(lhs),
+			rhs: Box::new(rhs)
+		})
+	}
+
+	//parses binary operators with a precedence of at least 'level'
+	fn parse_expression(tokens: &[Token], pos: &mut usize, level: usize, source: &str, err: &Error)
+	-> Result<Value, AssembleError> {
+		let levels = Operator::levels();
+		if level >= levels.len() {
+			return Value::create(tokens, pos, source, err);
+		}
+		let mut lhs = Value::parse_expression(tokens, pos, level + 1, source, err)?;
+		loop {
+			let op = match tokens.get(*pos) {
+				Some(token) => levels[level].iter()
+					.find(|&&(ref kind, _)| *kind == token.kind)
+					.map(|&(_, op)| op),
 				None => None
+			};
+			match op {
+				Some(op) => {
+					*pos += 1;
+					let rhs = Value::parse_expression(tokens, pos, level + 1, source, err)?;
+					lhs = Value::fold(op, lhs, rhs, err)?;
+				},
+				None => return Ok(lhs)
 			}
+		}
+	}
 
-		} else if value.chars().next() == Some('>') {
-			return match Value::create(&value[1..], err) {
-				Some (val) => {
-					if !val.is_ptr() {
-						err.throw(ErrorType::InvalidPointer(val));
+	//parses a single value from 'tokens', starting at 'pos'
+	fn create(tokens: &[Token], pos: &mut usize, source: &str, err: &Error) -> Result<Value, AssembleError> {
+		let invalid = || err.error(ErrorType::InvalidValue(source.to_string()));
+		let token = match tokens.get(*pos) {
+			Some(token) => token,
+			None => return Err(invalid())
+		};
+		*pos += 1;
+		match token.kind {
+			TokenKind::LParen => {
+				let ret = Value::parse_expression(tokens, pos, 0, source, err)?;
+				if tokens.get(*pos).map(|t| &t.kind) != Some(&TokenKind::RParen) {
+					return Err(invalid());
+				}
+				*pos += 1;
+				Ok(ret)
+			},
+			TokenKind::LBracket => {
+				//Is a pointer
+				let from_back = tokens.get(*pos).map(|t| &t.kind) == Some(&TokenKind::Minus);
+				if from_back {
+					*pos += 1;
+				}
+				let position = Value::parse_expression(tokens, pos, 0, source, err)?;
+				let length = match tokens.get(*pos).map(|t| &t.kind) {
+					Some(&TokenKind::Colon) => {
+						*pos += 1;
+						Value::parse_expression(tokens, pos, 0, source, err)?
+					},
+					_ => Value::Bignum(gmp::mpz::Mpz::one())
+				};
+				if tokens.get(*pos).map(|t| &t.kind) != Some(&TokenKind::RBracket) {
+					return Err(invalid());
+				}
+				*pos += 1;
+				//fields that are known now must be valid positions and sizes
+				for field in &[&position, &length] {
+					if let Some(num) = field.constant() {
+						if bignum_to_usize(&num).is_none() {
+							return Err(err.error(ErrorType::InvalidValue(
+								format!("{} in pointer {}", num, source)
+							)));
+						}
 					}
-					Some(Value::Position(Box::new(val), true))
 				}
-				None => None
-			}
+				Ok(Value::Pointer {
+					pos: Box::new(position),
+					len: Box::new(length),
+					rev: from_back
+				})
+			},
+			TokenKind::Minus => {
+				//negation, e.g. -5 or -(rax + 1)
+				let val = Value::create(tokens, pos, source, err)?;
+				Value::fold(Operator::Sub, Value::Bignum(gmp::mpz::Mpz::zero()), val, err)
+			},
+			TokenKind::Less | TokenKind::Greater => {
+				let val = Value::create(tokens, pos, source, err)?;
+				if !val.is_ptr() {
+					return Err(err.error(ErrorType::InvalidPointer(val)));
+				}
+				Ok(Value::Position(Box::new(val), token.kind == TokenKind::Greater))
+			},
+			TokenKind::Str(ref bytes) => Ok(Value::Boolvec(bytes_to_boolvec(bytes))),
+			TokenKind::Char(ref bytes) => Ok(Value::Boolvec(bytes_to_boolvec(bytes))),
+			TokenKind::Number(ref num) => match Value::parse_number(num) {
+				Ok(val) => Ok(val),
+				Err(kind) => Err(err.error(kind))
+			},
+			TokenKind::Ident(ref name) if name.starts_with('b') => {
+				let mut boolvec:Vec<bool> = vec![];
+				boolvec.reserve(name.len() - 1);
+				for c in name[1..].chars() {
+					boolvec.push(match c{
+						'0' => false,
+						'1' => true,
+						other => return Err(err.error(ErrorType::Generic(
+							format!("'{}' is not a valid character in a boolvec!", other)
+						)))
+					});
+				}
+				Ok(Value::Boolvec(boolvec))
+			},
+			_ => Err(invalid())
+		}
+	}
+}
 
-		} else {
-			//Is not a pointer
-			return match gmp::mpz::Mpz::from_str(value){
-				Ok(val) => Some(Value::Bignum(val)),
-				Err(_) => None,
-			};
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::span::Span;
+
+	fn error() -> Error {
+		Error::new(Span::new(String::new(), 1, (0, 0), None))
+	}
+
+	fn parse(text: &str) -> Value {
+		match Value::new(text, &error(), false) {
+			Ok(value) => value,
+			Err(e) => panic!("'{}' could not be parsed: {}", text, e)
+		}
+	}
+
+	//the value of a constant as a decimal string
+	fn number(text: &str) -> String {
+		match parse(text).constant() {
+			Some(num) => format!("{}", num),
+			None => panic!("'{}' is not constant", text)
+		}
+	}
+
+	fn width(text: &str) -> usize {
+		match parse(text) {
+			Value::Boolvec(ref bits) => bits.len(),
+			other => panic!("'{}' has no width, it is {}", text, other)
+		}
+	}
+
+	fn is_invalid(text: &str) -> bool {
+		Value::new(text, &error(), false).is_err()
+	}
+
+	#[test]
+	fn parses_number_literals() {
+		assert_eq!(number("1234"), "1234");
+		assert_eq!(number("1_000"), "1000");
+		assert_eq!(number("0xFF"), "255");
+		assert_eq!(number("0o17"), "15");
+		assert_eq!(number("0b1100"), "12");
+		assert_eq!(number("b0011"), "12");
+		assert_eq!(width("b0011"), 4);
+	}
+
+	#[test]
+	fn parses_width_annotated_literals() {
+		assert_eq!(number("8'hFF"), "255");
+		assert_eq!(width("8'hFF"), 8);
+		assert_eq!(number("16'd5"), "5");
+		assert_eq!(width("16'd5"), 16);
+		assert_eq!(number("6'o17"), "15");
+		assert_eq!(number("4'b1010"), "10");
+		assert_eq!(number("64'x1_0"), "16");
+	}
+
+	#[test]
+	fn parses_character_literals_like_strings() {
+		assert_eq!(number("'A'"), number("\"A\""));
+		assert_eq!(width("'\\n'"), 8);
+		assert_eq!(width("\"hi\""), 16);
+	}
+
+	#[test]
+	fn rejects_invalid_literals() {
+		assert!(is_invalid("8'h1FF"));
+		assert!(is_invalid("8'q1"));
+		assert!(is_invalid("x'h1"));
+		assert!(is_invalid("0xZZ"));
+		assert!(is_invalid("0b"));
+		assert!(is_invalid("b0121"));
+		assert!(is_invalid("12abc"));
+	}
+
+	#[test]
+	fn folds_constant_expressions() {
+		assert_eq!(number("(1 + 2) * 5"), "15");
+		assert_eq!(number("1 + 2 * 3"), "7");
+		assert_eq!(number("1 << 4 | 1"), "17");
+		assert_eq!(number("0xF0 & 0x3C ^ 1"), "49");
+		assert_eq!(number("17 / 5 + 17 % 5"), "5");
+		assert_eq!(number("256 >> 4"), "16");
+		assert_eq!(number("255 >> 18446744073709551616"), "0");
+		match parse("1 + 2") {
+			Value::Bignum(_) => {},
+			other => panic!("'1 + 2' should be folded, but is {}", other)
 		}
 	}
+
+	#[test]
+	fn compares_to_one_or_zero() {
+		assert_eq!(number("3 < 5"), "1");
+		assert_eq!(number("3 >= 5"), "0");
+		assert_eq!(number("2 == 3"), "0");
+		assert_eq!(number("2 != 3"), "1");
+		assert_eq!(number("1 + 1 == 2 & 4 <= 4"), "1");
+	}
+
+	#[test]
+	fn negates_values() {
+		assert_eq!(number("-(2 - 7)"), "5");
+		assert_eq!(number("10 - -2"), "12");
+		assert_eq!(number("-5 + 5"), "0");
+	}
+
+	#[test]
+	fn folds_pointer_fields() {
+		match parse("[64*2:17-1]") {
+			Value::Pointer{ref pos, ref len, rev: false} => {
+				assert_eq!(format!("{}", pos.constant().expect("constant position")), "128");
+				assert_eq!(format!("{}", len.constant().expect("constant length")), "16");
+			},
+			other => panic!("'[64*2:17-1]' should be a pointer, but is {}", other)
+		}
+		assert!(parse("[0:8] << 2").constant().is_none());
+		assert_eq!(number(">[100:32]"), "132");
+	}
+
+	#[test]
+	fn rejects_invalid_expressions() {
+		assert!(is_invalid("1 / 0"));
+		assert!(is_invalid("1 % (2 - 2)"));
+		assert!(is_invalid("1 << -1"));
+		assert!(is_invalid("1 << 18446744073709551616"));
+		assert!(is_invalid("1 << 100000000"));
+		assert!(is_invalid("[0:8-16]"));
+		assert!(is_invalid("[0-1:8]"));
+		assert!(is_invalid("(1 + 2"));
+		assert!(is_invalid("1 +"));
+		assert!(is_invalid("1 2"));
+		assert!(Value::new("5", &error(), true).is_err());
+	}
+
+	#[test]
+	fn checks_numbers_used_as_sizes() {
+		let env = Environment::new();
+		assert_eq!(parse("64").get_usize(&env).ok(), Some(64));
+		assert!(parse("18446744073709551616").get_usize(&env).is_err());
+		assert!(Value::Bignum(gmp::mpz::Mpz::zero() - gmp::mpz::Mpz::one()).get_usize(&env).is_err());
+	}
 }
@@ -0,0 +1,13 @@
+extern crate gmp;
+extern crate time;
+
+mod asm;
+
+pub use asm::assembler::Assembler;
+pub use asm::environment::Environment;
+pub use asm::value::{Operator, Value};
+pub use asm::instruction::Instruction;
+pub use asm::span::Span;
+pub use asm::error::{ArgumentType, AssembleError, Error, ErrorType, RuntimeError, RuntimeErrorType};
+pub use asm::format::format_source;
+pub use asm::lexer::is_shebang;
@@ -1,151 +1,614 @@
-extern crate gmp;
-extern crate time;
+extern crate bit_asm;
+use bit_asm::{Assembler, AssembleError, Environment};
+use bit_asm::{format_source, is_shebang};
+use std::env;
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::BufRead;
-use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::process::{Command, Stdio};
 use std::str::FromStr;
-use asm::assembler::Assembler;
-use asm::environment::Environment;
-use std::env;
-use std::collections::HashMap;
 
-mod asm;
+//exit codes, following the BSD sysexits convention
+const EXIT_SUCCESS:i32 = 0;
+const EXIT_FAILURE:i32 = 1;
+const EXIT_USAGE:i32 = 64;
+const EXIT_ASSEMBLY:i32 = 65;
+const EXIT_RUNTIME:i32 = 70;
 
-fn load_text(asm: &mut Assembler, code: &str) {
-	let mut linenum = 0;
-	for line in code.lines() {
-		for s in line.split(';') {
-			linenum += 1;
-			asm.parse_line(&s.to_string(), linenum, None);
+//tests that don't give --max-steps fail after this many instructions instead of running forever
+const TEST_MAX_STEPS:usize = 1_000_000;
+
+const USAGE:&'static str = "Usage:
+    bit-asm [run] [options] {file}.asm [{argument}...]
+    bit-asm [run] [options] --text {assembly} [-- {argument}...]
+    bit-asm check [options] {file}.asm
+    bit-asm fmt [--write] {file}.asm...
+    bit-asm disasm [options] {file}.asm
+    bit-asm test [options] {file or directory}...
+    bit-asm repl [options]
+
+Commands:
+    run                     assembles and runs a program, this is the default
+    check                   assembles a program without running it
+    fmt                     prints a program with consistent indentation and spacing
+    disasm                  prints every instruction of a program with its index
+    test                    runs programs and compares their output with {file}.out
+    repl                    reads and runs one line or block at a time
+
+Options:
+    -f, --file {file}.asm         the program to use, the same as giving it without '--file'
+    -c, --text {assembly}         uses 'assembly' as the program instead of a file, statements
+                                  can be separated by newlines or '\\n', and '-' reads stdin
+    -D, --define {name}[=value]   defines a name before the program is read, can be repeated
+    -I, --include-path {dir}      searches 'dir' for included files, can be repeated
+    -E, --preprocess              prints the program after macros, includes and defines
+    -t, --trace                   prints the location of each instruction as it runs
+    -s, --print-stack [bits]      prints the stack as numbers of 'bits' bits, 64 by default,
+                                  once the program ends
+    -p, --print-parsed            prints each instruction as it is parsed
+        --seed {number}           seeds 'ext random' so every run gives the same numbers
+        --max-steps {number}      stops the program with an error after 'number' instructions,
+                                  tests stop after 1000000 by default
+    -w, --write                   makes fmt overwrite files instead of printing them
+    -h, --help                    prints this message
+    -V, --version                 prints the version of bit-asm
+
+Exit codes:
+    0                             the program ran successfully, unless it halted with another status
+    64                            the command line is invalid
+    65                            the program could not be assembled
+    70                            the program stopped with a runtime error
+    a program that halts with 64, 65 or 70 itself exits with the same code as these errors,
+    so check stderr to tell them apart";
+
+#[derive(PartialEq, Clone, Copy)]
+enum Subcommand {
+	Run,
+	Check,
+	Fmt,
+	Disasm,
+	Test,
+	Repl
+}
+
+struct Options {
+	command:       Subcommand,
+	files:         Vec<String>,
+	//the arguments after '--', which are given to the program
+	args:          Vec<String>,
+	text:          Option<String>,
+	defines:       Vec<(String, String)>,
+	include_paths: Vec<String>,
+	preprocess:    bool,
+	trace:         bool,
+	print_stack:   Option<usize>,
+	seed:          Option<u64>,
+	max_steps:     Option<usize>,
+	print_parsed:  bool,
+	write:         bool,
+	help:          bool,
+	version:       bool
+}
+
+impl Subcommand {
+	fn from_name(name: &str) -> Option<Subcommand> {
+		match name {
+			"run" => Some(Subcommand::Run),
+			"check" => Some(Subcommand::Check),
+			"fmt" => Some(Subcommand::Fmt),
+			"disasm" => Some(Subcommand::Disasm),
+			"test" => Some(Subcommand::Test),
+			"repl" => Some(Subcommand::Repl),
+			_ => None
 		}
 	}
 }
 
-fn load_file(asm: &mut Assembler, file_name:&str) {
-	let file = File::open(file_name).unwrap();
-	let buffer = BufReader::new(&file);
-	let mut linenum = 0;
-	for line in buffer.lines() {
-		let l:String = line.unwrap();
-		linenum += 1;
-		asm.parse_line(&l, linenum, Some(file_name.to_string()));
+//the long name of an option and whether it takes a value
+fn find_option(name: &str) -> Option<(&'static str, bool)> {
+	let options = [
+		("file", "f", true),
+		("text", "c", true),
+		("define", "D", true),
+		("include-path", "I", true),
+		("preprocess", "E", false),
+		("trace", "t", false),
+		("print-stack", "s", true),
+		("print-parsed", "p", false),
+		("seed", "", true),
+		("max-steps", "", true),
+		("write", "w", false),
+		("help", "h", false),
+		("version", "V", false),
+	];
+	options.iter()
+		.find(|option| option.0 == name || option.1 == name)
+		.map(|option| (option.0, option.2))
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+	let mut options = Options {
+		command:       Subcommand::Run,
+		files:         Vec::new(),
+		args:          Vec::new(),
+		text:          None,
+		defines:       Vec::new(),
+		include_paths: Vec::new(),
+		preprocess:    false,
+		trace:         false,
+		print_stack:   None,
+		seed:          None,
+		max_steps:     None,
+		print_parsed:  false,
+		write:         false,
+		help:          false,
+		version:       false
+	};
+	let mut i = 0;
+	let mut seen_positional = false;
+	while i < args.len() {
+		let arg = &args[i];
+		i += 1;
+		if arg == "--" {
+			options.args = args[i..].to_vec();
+			break;
+		}
+
+		//options look like '--name', '--name=value', '-n', or '-nvalue'
+		let (name, mut value) = if arg.starts_with("--") && arg.len() > 2 {
+			match arg.find('=') {
+				Some(pos) => (&arg[2..pos], Some(arg[pos + 1..].to_string())),
+				None => (&arg[2..], None)
+			}
+		} else if arg.starts_with('-') && arg.len() > 1 {
+			let split = 1 + arg[1..].chars().next().map(|c| c.len_utf8()).unwrap_or(0);
+			match split < arg.len() {
+				true => (&arg[1..split], Some(arg[split..].to_string())),
+				false => (&arg[1..], None)
+			}
+		} else {
+			match (seen_positional, Subcommand::from_name(arg)) {
+				(false, Some(command)) => options.command = command,
+				_ => options.files.push(arg.clone())
+			}
+			seen_positional = true;
+
+			//everything after the program that is run belongs to it, like with other interpreters
+			if options.command == Subcommand::Run && options.files.len() == 1 {
+				if args.get(i).map_or(false, |next| next == "--") {
+					i += 1;
+				}
+				options.args = args[i..].to_vec();
+				break;
+			}
+			continue;
+		};
+
+		let (name, takes_value) = match find_option(name) {
+			Some(option) => option,
+			None => return Err(format!("unknown option '{}'", arg))
+		};
+		//'--print-stack' on its own prints 64 bit numbers, like it always has
+		let next_is_number = args.get(i).map_or(false, |next| usize::from_str(next).is_ok());
+		if name == "print-stack" && value.is_none() && !next_is_number {
+			value = Some("64".to_string());
+		}
+		if takes_value && value.is_none() {
+			value = args.get(i).cloned();
+			i += 1;
+		}
+		let value = match (takes_value, value) {
+			(true, Some(value)) => value,
+			(true, None) => return Err(format!("option '--{}' needs a value", name)),
+			(false, Some(_)) => return Err(format!("option '--{}' does not take a value", name)),
+			(false, None) => String::new()
+		};
+		match name {
+			//the same as giving the file without an option
+			"file" => {
+				options.files.push(value);
+				seen_positional = true;
+			},
+			"text" => options.text = Some(value),
+			"define" => options.defines.push(match value.find('=') {
+				Some(pos) => (value[..pos].to_string(), value[pos + 1..].to_string()),
+				None => (value, "1".to_string())
+			}),
+			"include-path" => options.include_paths.push(value),
+			"preprocess" => options.preprocess = true,
+			"trace" => options.trace = true,
+			"print-stack" => options.print_stack = match usize::from_str(&value) {
+				Ok(bits) if bits > 0 => Some(bits),
+				_ => return Err(format!("'{}' is not a valid number of bits", value))
+			},
+			"print-parsed" => options.print_parsed = true,
+			"seed" => options.seed = match u64::from_str(&value) {
+				Ok(seed) => Some(seed),
+				_ => return Err(format!("'{}' is not a valid seed", value))
+			},
+			"max-steps" => options.max_steps = match usize::from_str(&value) {
+				Ok(steps) => Some(steps),
+				_ => return Err(format!("'{}' is not a valid number of steps", value))
+			},
+			"write" => options.write = true,
+			"help" => options.help = true,
+			_ => options.version = true
+		}
 	}
+	Ok(options)
 }
 
-enum Req {
-	Yes,
-	Maybe,
-	No
+fn usage_error(message: &str) -> ! {
+	eprintln!("bit-asm: {}", message);
+	eprintln!("type 'bit-asm --help' for help on how to use bit assembly");
+	process::exit(EXIT_USAGE);
 }
 
-struct ArgType {
-	name:String,
-	short:Option<String>,
-	arg:Req
+fn print_diagnostics(asm: &Assembler) {
+	for diagnostic in &asm.diagnostics {
+		eprintln!("{}", diagnostic);
+	}
 }
 
-fn main() {
-	let valid_args = vec![
-		ArgType{name:"help".to_string(), short:Some("h".to_string()), arg:Req::No},
-		ArgType{name:"file".to_string(), short:Some("h".to_string()), arg:Req::Yes},
-		ArgType{name:"text".to_string(), short:Some("h".to_string()), arg:Req::Yes},
-		ArgType{name:"print-stack".to_string(),  short:Some("s".to_string()), arg:Req::Maybe},
-		ArgType{name:"print-parsed".to_string(), short:Some("p".to_string()), arg:Req::No},
-	];
+fn new_assembler(options: &Options) -> Assembler {
+	let mut asm = Assembler::new(options.print_parsed);
+	asm.trace = options.trace;
+	asm.preprocess = options.preprocess;
+	for &(ref name, ref value) in &options.defines {
+		asm.define(name, value);
+	}
+	for path in &options.include_paths {
+		asm.add_include_path(path);
+	}
+	asm
+}
 
-	let mut args:HashMap<String, String> = HashMap::new();
+fn new_environment(options: &Options) -> Environment {
+	let mut env = Environment::new();
+	env.args = options.args.clone();
+	env.step_limit = options.max_steps;
+	if let Some(seed) = options.seed {
+		env.randstate.seed_ui(seed);
+	}
+	env
+}
 
-	let mut current_arg_name:String = "file".to_string();
+//reads a whole program from stdin, for '-' in place of a file or text
+fn read_stdin() -> String {
+	let mut text = String::new();
+	if let Err(e) = io::stdin().read_to_string(&mut text) {
+		eprintln!("could not read the program from stdin: {}", e);
+		process::exit(EXIT_FAILURE);
+	}
+	text
+}
 
-	let mut is_first = true;
-	for element in env::args().collect::<Vec<String>>() {
-		if is_first {
-			is_first = false;
-			continue;
+//reads the program given on the command line, exits if it can't be assembled
+fn assemble(options: &Options) -> Assembler {
+	let mut asm = new_assembler(options);
+	let mut result = match (&options.text, options.files.len()) {
+		(&Some(ref text), 0) if text == "-" => asm.load_text(&read_stdin()),
+		(&Some(ref text), 0) => asm.load_text(text),
+		(&None, 1) if options.files[0] == "-" => asm.load_text(&read_stdin()),
+		(&None, 1) => asm.load_file(&options.files[0]),
+		(&None, 0) => usage_error("no program was given"),
+		_ => usage_error("only one program can be given")
+	};
+	if result.is_ok() && !options.preprocess {
+		result = asm.link();
+	}
+	print_diagnostics(&asm);
+	if let Err(e) = result {
+		eprintln!("{}", e);
+		process::exit(EXIT_ASSEMBLY);
+	}
+	asm
+}
+
+fn run(options: &Options) -> i32 {
+	let mut asm = assemble(options);
+	if options.preprocess {
+		print!("{}", asm.preprocessed());
+		return EXIT_SUCCESS;
+	}
+	let mut env = new_environment(options);
+	let result = asm.run(&mut env);
+	if let Some(bits) = options.print_stack {
+		env.print_bytes(bits);
+	}
+	match result {
+		Ok(status) => status,
+		Err(e) => {
+			eprintln!("{}", e);
+			EXIT_RUNTIME
 		}
-		//argument
-		if element.len() >= 2 && &element[0..2] == "--" {
-			let element = &element[2..];
-			let does_contain = valid_args.iter().filter(
-				|a| a.name == element
-			).count() != 0;
+	}
+}
 
-			if !does_contain {
-				panic!("No such argument of name '{}'!", element);
-			}
+fn check(options: &Options) -> i32 {
+	let asm = assemble(options);
+	if options.preprocess {
+		print!("{}", asm.preprocessed());
+	}
+	EXIT_SUCCESS
+}
 
-			current_arg_name = element.to_string();
+fn disasm(options: &Options) -> i32 {
+	print!("{}", assemble(options).disassemble());
+	EXIT_SUCCESS
+}
 
-			args.insert(current_arg_name.clone(), "".to_string());
+fn fmt(options: &Options) -> i32 {
+	if options.files.is_empty() {
+		usage_error("no files to format were given");
+	}
+	let mut status = EXIT_SUCCESS;
+	for file in &options.files {
+		let formatted = fs::read_to_string(file)
+			.map_err(|e| format!("could not read file '{}': {}", file, e))
+			.and_then(|source| format_source(&source, Some(file.clone())).map_err(|e| format!("{}", e)));
+		let result = match (formatted, options.write) {
+			(Ok(formatted), true) => fs::write(file, formatted)
+				.map_err(|e| format!("could not write file '{}': {}", file, e)),
+			(Ok(formatted), false) => {
+				print!("{}", formatted);
+				Ok(())
+			},
+			(Err(e), _) => Err(e)
+		};
+		if let Err(e) = result {
+			eprintln!("{}", e);
+			status = EXIT_ASSEMBLY;
+		}
+	}
+	status
+}
 
-		//shortened argument
-		} else if element.len() >= 1 && &element[0..1] == "-" {
-			let element = &element[1..];
-			let mut arg_name = "".to_string();
-			for arg in &valid_args {
-				if arg.short == Some(element.to_string()) {
-					arg_name = arg.name.clone();
-					break;
-				}
-			}
+//every .asm file in 'path' if it is a directory, or just 'path'
+fn find_tests(path: &Path, tests: &mut Vec<PathBuf>) -> io::Result<()> {
+	if !path.is_dir() {
+		tests.push(path.to_path_buf());
+		return Ok(());
+	}
+	let mut entries:Vec<PathBuf> = fs::read_dir(path)?
+		.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+		.collect();
+	entries.sort();
+	for entry in entries {
+		if entry.is_dir() || entry.extension().map_or(false, |ext| ext == "asm") {
+			find_tests(&entry, tests)?;
+		}
+	}
+	Ok(())
+}
 
-			if arg_name == "" {
-				panic!("No such argument of name '{}'!", element);
-			} else {
-				current_arg_name = arg_name;
+//runs a test program in a new process, with {name}.in as its input if it exists,
+//and compares its output with {name}.out if that exists
+fn run_test(test: &Path, options: &Options) -> Result<(), String> {
+	let exe = env::current_exe().map_err(|e| format!("{}", e))?;
+	let mut command = Command::new(exe);
+	command.arg("run");
+	for &(ref name, ref value) in &options.defines {
+		command.arg(format!("--define={}={}", name, value));
+	}
+	for path in &options.include_paths {
+		command.arg(format!("--include-path={}", path));
+	}
+	if let Some(seed) = options.seed {
+		command.arg(format!("--seed={}", seed));
+	}
+	command.arg(format!("--max-steps={}", options.max_steps.unwrap_or(TEST_MAX_STEPS)));
+	if options.trace {
+		command.arg("--trace");
+	}
+	command.arg(test);
+	let input = test.with_extension("in");
+	command.stdin(match input.is_file() {
+		true => Stdio::from(File::open(&input).map_err(|e| format!("{}", e))?),
+		false => Stdio::null()
+	});
+	let output = command.output().map_err(|e| format!("{}", e))?;
+	if !output.status.success() {
+		return Err(format!("exited with {}\n{}", output.status, String::from_utf8_lossy(&output.stderr)));
+	}
+	let expected = test.with_extension("out");
+	if expected.is_file() {
+		let expected = fs::read(&expected).map_err(|e| format!("{}", e))?;
+		if expected != output.stdout {
+			return Err(format!("expected output:\n{}\nactual output:\n{}",
+				String::from_utf8_lossy(&expected),
+				String::from_utf8_lossy(&output.stdout)
+			));
+		}
+	}
+	Ok(())
+}
+
+fn test(options: &Options) -> i32 {
+	let mut tests = Vec::new();
+	let paths = match options.files.len() {
+		0 => vec![".".to_string()],
+		_ => options.files.clone()
+	};
+	for path in &paths {
+		if let Err(e) = find_tests(Path::new(path), &mut tests) {
+			eprintln!("could not read '{}': {}", path, e);
+			return EXIT_FAILURE;
+		}
+	}
+	let mut failed = 0;
+	for test in &tests {
+		match run_test(test, options) {
+			Ok(_) => println!("test {} ... ok", test.display()),
+			Err(reason) => {
+				println!("test {} ... FAILED\n{}", test.display(), reason);
+				failed += 1;
 			}
+		}
+	}
+	println!("\ntest result: {}. {} passed; {} failed",
+		match failed {
+			0 => "ok",
+			_ => "FAILED"
+		},
+		tests.len() - failed,
+		failed
+	);
+	match failed {
+		0 => EXIT_SUCCESS,
+		_ => EXIT_FAILURE
+	}
+}
 
-			args.insert(current_arg_name.clone(), "".to_string());
+//assembles and runs every line as soon as it is typed, keeping the stack between lines,
+//lines inside of a block are only run once the block ends
+fn repl(options: &Options) -> i32 {
+	let mut asm = new_assembler(options);
+	let mut env = new_environment(options);
+	let stdin = io::stdin();
+	let mut linenum = 0;
+	let mut start = 0;
+	loop {
+		print!("{}", match asm.in_block() {
+			true => "... ",
+			false => "> "
+		});
+		io::stdout().flush().ok();
+		let mut line = String::new();
+		match stdin.lock().read_line(&mut line) {
+			Ok(0) => break,
+			Ok(_) => {},
+			Err(e) => {
+				eprintln!("{}", e);
+				return EXIT_FAILURE;
+			}
+		}
+		linenum += 1;
+		if !asm.in_block() {
+			start = asm.len();
+		}
+		let result:Result<(), AssembleError> = asm.parse_line(&line.trim_end().to_string(), linenum, None)
+			.and_then(|_| match asm.in_block() {
+				true => Ok(()),
+				false => asm.link()
+			});
+		print_diagnostics(&asm);
+		asm.diagnostics.clear();
+		if let Err(e) = result {
+			eprintln!("{}", e);
+			asm.truncate(start);
+			continue;
+		}
+		if asm.in_block() {
+			continue;
+		}
 
-		//not an argument
-		} else {
-			println!("{}", element);
-			let arg = args.get_mut(&current_arg_name).expect("No argument");
-			arg.push_str(element.as_ref());
+		match asm.run(&mut env) {
+			Ok(status) if env.exit_status.is_some() => return status,
+			Ok(_) => {},
+			Err(e) => {
+				eprintln!("{}", e);
+				env.instruction = asm.len();
+			}
 		}
 	}
+	EXIT_SUCCESS
+}
 
-	let do_print_parsed = args.contains_key("print-parsed");
-	let do_stack_print = args.contains_key("print-stack");
+//the options on a line such as '; bit-asm: --seed=42 --max-steps=100000' at the start of a program,
+//which may only come after a shebang line
+fn read_pragma(file: &str) -> Vec<String> {
+	let file = match File::open(file) {
+		Ok(file) => file,
+		Err(_) => return Vec::new()
+	};
+	let lines = io::BufReader::new(file).lines()
+		.take(2)
+		.filter_map(|line| line.ok())
+		.enumerate()
+		.find(|&(i, ref line)| !(i == 0 && is_shebang(line)));
+	let line = match lines {
+		Some((_, line)) => line,
+		None => return Vec::new()
+	};
+	let line = line.trim_start();
+	match line.starts_with(';') && line[1..].trim_start().starts_with("bit-asm:") {
+		true => line[1..].trim_start()["bit-asm:".len()..]
+			.split_whitespace()
+			.map(|arg| arg.to_string())
+			.collect(),
+		false => Vec::new()
+	}
+}
 
-	let mut env = Environment::new();
-	let mut asm = Assembler::new(do_print_parsed);
-	let mut do_run = false;
+//options that only change how a program runs, which are the only ones a program can set itself
+const PRAGMA_OPTIONS:&'static [&'static str] = &["define", "include-path", "seed", "max-steps", "trace", "print-stack"];
 
-	if args.contains_key("help") {
-		println!("{}",
-"Welcome to Bit Assembly!
-For help on how to write Bit Assembly, refer to the 'doc.md' file.
+fn check_pragma(pragma: &[String]) -> Result<(), String> {
+	for arg in pragma.iter().filter(|arg| arg.starts_with('-')) {
+		let name = match arg.starts_with("--") {
+			true => arg[2..].split('=').next().unwrap_or(""),
+			false => arg.get(1..2).unwrap_or("")
+		};
+		match find_option(name) {
+			Some((name, _)) if PRAGMA_OPTIONS.contains(&name) => {},
+			_ => return Err(format!("'{}' can't be used there, only --{}",
+				arg, PRAGMA_OPTIONS.join(", --")
+			))
+		}
+	}
+	let options = parse_options(pragma)?;
+	match options.command == Subcommand::Run && options.files.is_empty() && options.args.is_empty() {
+		true => Ok(()),
+		false => Err("commands, files and arguments can't be used there".to_string())
+	}
+}
 
-Usage:
-    bit-asm --file {file name}.asm
-    bit-asm --text {assembly}
+fn main() {
+	let args:Vec<String> = env::args().skip(1).collect();
+	let mut options = match parse_options(&args) {
+		Ok(options) => options,
+		Err(e) => usage_error(&e)
+	};
 
-Options:
-    --print-stack {bits}    prints stack as a sequence of bytes
-    --print-parsed          prints each line as they are parsed");
-
-	} else if args.contains_key("file") {
-		load_file(&mut asm, args.get("file").expect("This shouldnt happen"));
-		do_run = true;
-
-	} else if args.contains_key("text") {
-		load_text(&mut asm, args.get("text").expect("This shouldnt happen"));
-		do_run = true;
-
-	} else {
-		println!("type 'bit-asm --help' for help on how to use bit assembly");
-	}
-	if do_run {
-		asm.run(&mut env);
-		if do_stack_print {
-			let bits = usize::from_str(
-				match args.get("print-stack").unwrap_or(&"64".to_string()).as_ref() {
-					"" => "64",
-					other => other
-				}
-			).expect("print-stack argument is not valid!");
-			env.print_bytes(bits);
+	//options from the program itself come first, so the command line can override them
+	let assembles = options.command == Subcommand::Run
+		|| options.command == Subcommand::Check
+		|| options.command == Subcommand::Disasm;
+	if assembles && options.text.is_none() && options.files.len() == 1 && options.files[0] != "-" {
+		let pragma = read_pragma(&options.files[0]);
+		if !pragma.is_empty() {
+			if let Err(e) = check_pragma(&pragma) {
+				usage_error(&format!("the options at the start of '{}' are invalid: {}", options.files[0], e));
+			}
+			let combined:Vec<String> = pragma.into_iter().chain(args.into_iter()).collect();
+			options = match parse_options(&combined) {
+				Ok(options) => options,
+				Err(e) => usage_error(&e)
+			};
 		}
 	}
+
+	if options.help {
+		println!("Welcome to Bit Assembly!\nFor help on how to write Bit Assembly, refer to the 'doc.md' file.\n\n{}", USAGE);
+		return;
+	}
+	if options.version {
+		println!("bit-asm {}", env!("CARGO_PKG_VERSION"));
+		return;
+	}
+	let status = match options.command {
+		Subcommand::Run => run(&options),
+		Subcommand::Check => check(&options),
+		Subcommand::Fmt => fmt(&options),
+		Subcommand::Disasm => disasm(&options),
+		Subcommand::Test => test(&options),
+		Subcommand::Repl => repl(&options)
+	};
+	process::exit(status);
 }